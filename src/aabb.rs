@@ -0,0 +1,123 @@
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3};
+
+/// Axis-aligned bounding box.
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub x: Interval,
+    pub y: Interval,
+    pub z: Interval,
+}
+
+impl Aabb {
+    pub fn new(x: Interval, y: Interval, z: Interval) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn empty() -> Self {
+        Self::new(Interval::empty(), Interval::empty(), Interval::empty())
+    }
+
+    /// Box spanned by two corner points, in any order.
+    pub fn from_points(a: Point3, b: Point3) -> Self {
+        Self::new(
+            Interval::new(a.x().min(b.x()), a.x().max(b.x())),
+            Interval::new(a.y().min(b.y()), a.y().max(b.y())),
+            Interval::new(a.z().min(b.z()), a.z().max(b.z())),
+        )
+    }
+
+    pub fn surrounding(a: &Aabb, b: &Aabb) -> Self {
+        Self::new(
+            Interval::enclosing(&a.x, &b.x),
+            Interval::enclosing(&a.y, &b.y),
+            Interval::enclosing(&a.z, &b.z),
+        )
+    }
+
+    pub fn axis_interval(&self, n: usize) -> &Interval {
+        match n {
+            1 => &self.y,
+            2 => &self.z,
+            _ => &self.x,
+        }
+    }
+
+    pub fn center(&self) -> Point3 {
+        Point3::new(
+            (self.x.min + self.x.max) * 0.5,
+            (self.y.min + self.y.max) * 0.5,
+            (self.z.min + self.z.max) * 0.5,
+        )
+    }
+
+    pub fn diagonal(&self) -> Vec3 {
+        Vec3::new(self.x.size(), self.y.size(), self.z.size())
+    }
+
+    /// Index of the axis with the largest extent.
+    pub fn longest_axis(&self) -> usize {
+        let d = self.diagonal();
+        if d.x() > d.y() && d.x() > d.z() {
+            0
+        } else if d.y() > d.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        let origin = ray.origin();
+        let direction = ray.direction();
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+
+        for axis in 0..3 {
+            let ax = self.axis_interval(axis);
+            let adinv = 1.0 / direction[axis];
+
+            let t0 = (ax.min - origin[axis]) * adinv;
+            let t1 = (ax.max - origin[axis]) * adinv;
+
+            let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+
+            if t_max < t_min {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hit() {
+        let bbox = Aabb::from_points(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+        let towards = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let away = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, -1.0));
+        let beside = Ray::new(Point3::new(2.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+
+        assert!(bbox.hit(&towards, 0.0, f64::INFINITY));
+        assert!(!bbox.hit(&away, 0.0, f64::INFINITY));
+        assert!(!bbox.hit(&beside, 0.0, f64::INFINITY));
+    }
+
+    #[test]
+    fn test_surrounding() {
+        let a = Aabb::from_points(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0));
+        let b = Aabb::from_points(Point3::new(2.0, -1.0, 0.5), Point3::new(3.0, 0.5, 0.7));
+        let c = Aabb::surrounding(&a, &b);
+        assert_eq!(c.x.min, 0.0);
+        assert_eq!(c.x.max, 3.0);
+        assert_eq!(c.y.min, -1.0);
+        assert_eq!(c.longest_axis(), 0);
+    }
+}
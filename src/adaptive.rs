@@ -0,0 +1,145 @@
+/// Settings for spending samples where the image is still noisy. Each pixel
+/// gets at least `min_samples` and then stops once the standard error of its
+/// mean luminance falls below `threshold` times that luminance, or at
+/// `max_samples`. Pixels are checked between passes of `min_samples`
+/// samples, so a pass's stratified pattern is never cut short.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveSampling {
+    pub min_samples: u32,
+    pub max_samples: u32,
+    pub threshold: f64,
+    /// Also write an image of how many samples each pixel took, next to the
+    /// render.
+    pub write_heatmap: bool,
+}
+
+impl Default for AdaptiveSampling {
+    fn default() -> Self {
+        Self {
+            min_samples: 16,
+            max_samples: 1024,
+            threshold: 0.01,
+            write_heatmap: false,
+        }
+    }
+}
+
+/// Luminance below which the noise threshold stops shrinking, so nearly
+/// black pixels don't soak up samples to resolve invisible differences.
+const MIN_LUMINANCE: f64 = 0.01;
+
+impl AdaptiveSampling {
+    /// Whether a pixel with these statistics has had enough samples.
+    pub fn is_done(&self, variance: &PixelVariance) -> bool {
+        let count = variance.count();
+        if count < self.min_samples.max(2) {
+            return false;
+        }
+        if count >= self.max_samples {
+            return true;
+        }
+        variance.relative_error() <= self.threshold
+    }
+}
+
+/// Running mean and variance of a pixel's sample luminances, by Welford's
+/// method.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PixelVariance {
+    count: u32,
+    mean: f64,
+    m2: f64,
+}
+
+impl PixelVariance {
+    pub(crate) fn from_parts(count: u32, mean: f64, m2: f64) -> Self {
+        Self { count, mean, m2 }
+    }
+
+    /// Sum of squared differences from the mean, kept for the variance.
+    pub(crate) fn m2(&self) -> f64 {
+        self.m2
+    }
+
+    pub fn add(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Unbiased variance of the samples.
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        self.m2 / (self.count - 1) as f64
+    }
+
+    /// Standard error of the mean.
+    pub fn standard_error(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        (self.variance() / self.count as f64).sqrt()
+    }
+
+    /// Standard error relative to the mean, infinite until there are enough
+    /// samples to tell.
+    pub fn relative_error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        self.standard_error() / self.mean.max(MIN_LUMINANCE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_running_variance() {
+        let mut v = PixelVariance::default();
+        for x in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            v.add(x);
+        }
+        assert_eq!(v.count(), 8);
+        assert!((v.mean() - 5.0).abs() < 1e-12);
+        assert!((v.variance() - 32.0 / 7.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_stopping_rule() {
+        let settings = AdaptiveSampling {
+            min_samples: 4,
+            max_samples: 64,
+            threshold: 0.05,
+            write_heatmap: false,
+        };
+
+        let mut flat = PixelVariance::default();
+        for _ in 0..3 {
+            flat.add(0.5);
+        }
+        assert!(!settings.is_done(&flat));
+        flat.add(0.5);
+        assert!(settings.is_done(&flat));
+
+        let mut noisy = PixelVariance::default();
+        for i in 0..63 {
+            noisy.add(if i % 2 == 0 { 0.0 } else { 1.0 });
+        }
+        assert!(!settings.is_done(&noisy));
+        noisy.add(0.0);
+        assert!(settings.is_done(&noisy));
+    }
+}
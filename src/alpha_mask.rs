@@ -0,0 +1,163 @@
+use crate::bababoi::hash_to_unit;
+use crate::color::luminance;
+use crate::hittable::{HitRecord, Hittable};
+use crate::light::Light;
+use crate::material::Lambertian;
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::vec3::{Color, Point3, Vec3};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// Rays shot at each light to estimate how much of it the mask leaves.
+const COVERAGE_PROBES: usize = 64;
+
+/// Cuts holes in an object with an opacity texture, for leaves, fences and
+/// similar geometry. Rays pass through where the opacity is zero, as if the
+/// surface wasn't there; partial opacity lets that share of rays through.
+pub struct AlphaMask {
+    object: Box<dyn Hittable>,
+    alpha: Arc<dyn Texture>,
+}
+
+impl AlphaMask {
+    pub fn new(object: Box<dyn Hittable>, alpha: Arc<dyn Texture>) -> Self {
+        Self { object, alpha }
+    }
+
+    fn is_opaque(&self, rec: &HitRecord) -> bool {
+        let alpha = self.alpha.scalar(rec.u, rec.v, &rec.p);
+        alpha >= 1.0 || (alpha > 0.0 && hash_to_unit(&[rec.p.x(), rec.p.y(), rec.p.z()]) < alpha)
+    }
+
+    /// Average opacity over the emitting surface inside the light's bounds,
+    /// or `None` if no probe found it.
+    fn coverage(&self, light: &Light) -> Option<f64> {
+        let bounds = light.bounding_box();
+        let reach = bounds.diagonal().length();
+        let mut rec = HitRecord::new(
+            Point3::zero(),
+            Vec3::zero(),
+            Lambertian::new(Color::zero()),
+            0.0,
+        );
+        let (mut opacity, mut hits) = (0.0, 0);
+
+        for i in 0..COVERAGE_PROBES {
+            let h = |k: f64| hash_to_unit(&[i as f64, k]);
+            let target = Point3::new(
+                bounds.x.min + h(0.0) * (bounds.x.max - bounds.x.min),
+                bounds.y.min + h(1.0) * (bounds.y.max - bounds.y.min),
+                bounds.z.min + h(2.0) * (bounds.z.max - bounds.z.min),
+            );
+            let z = 1.0 - 2.0 * h(3.0);
+            let phi = 2.0 * PI * h(4.0);
+            let r = (1.0 - z * z).sqrt();
+            let from = target + Vec3::new(r * phi.cos(), r * phi.sin(), z) * reach;
+
+            let ray = Ray::new(from, target - from);
+            if self.object.hit(&ray, 0.0, f64::INFINITY, &mut rec)
+                && luminance(rec.mat.emitted(&ray, &rec)) > 0.0
+            {
+                opacity += self.alpha.scalar(rec.u, rec.v, &rec.p).clamp(0.0, 1.0);
+                hits += 1;
+            }
+        }
+        (hits > 0).then(|| opacity / hits as f64)
+    }
+}
+
+impl Hittable for AlphaMask {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let mut t_min = t_min;
+        while self.object.hit(ray, t_min, t_max, rec) {
+            if self.is_opaque(rec) {
+                return true;
+            }
+            // Look for the next surface of the object behind the cutout.
+            t_min = rec.t;
+        }
+        false
+    }
+
+    /// Lights the mask cuts away entirely are dropped, and the rest are
+    /// picked in proportion to how much of them is left.
+    fn collect_lights(&self, lights: &mut Vec<Light>) {
+        let mut inner = Vec::new();
+        self.object.collect_lights(&mut inner);
+        for mut light in inner {
+            match self.coverage(&light) {
+                Some(coverage) if coverage <= 0.0 => continue,
+                Some(coverage) => light.scale_power(coverage),
+                None => {}
+            }
+            lights.push(light);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::DiffuseLight;
+    use crate::sphere::Sphere;
+    use crate::texture::{CheckerTexture, SolidColor};
+
+    fn sphere() -> Box<dyn Hittable> {
+        Box::new(Sphere::new(
+            Point3::zero(),
+            1.0,
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        ))
+    }
+
+    fn new_record() -> HitRecord {
+        HitRecord::new(
+            Point3::zero(),
+            Vec3::zero(),
+            Lambertian::new(Color::zero()),
+            0.0,
+        )
+    }
+
+    #[test]
+    fn test_transparent_mask_hides_object() {
+        let masked = AlphaMask::new(sphere(), SolidColor::gray(0.0));
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(!masked.hit(&ray, 0.001, f64::INFINITY, &mut new_record()));
+    }
+
+    #[test]
+    fn test_cutout_reveals_back_face() {
+        // Opaque only for z < 0, so the front of the sphere is cut away.
+        let alpha = CheckerTexture::from_colors(10.0, Color::zero(), Color::new(1.0, 1.0, 1.0));
+        let masked = AlphaMask::new(sphere(), alpha);
+        let ray = Ray::new(Point3::new(0.5, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec = new_record();
+        assert!(masked.hit(&ray, 0.001, f64::INFINITY, &mut rec));
+        assert!(rec.p.z() < 0.0);
+        assert!(!rec.front_face);
+    }
+
+    #[test]
+    fn test_mask_scales_lights() {
+        let lamp = || -> Box<dyn Hittable> {
+            Box::new(Sphere::new(
+                Point3::zero(),
+                1.0,
+                DiffuseLight::new(Color::new(1.0, 1.0, 1.0)),
+            ))
+        };
+        let mut unmasked = Vec::new();
+        lamp().collect_lights(&mut unmasked);
+
+        let mut hidden = Vec::new();
+        AlphaMask::new(lamp(), SolidColor::gray(0.0)).collect_lights(&mut hidden);
+        assert!(hidden.is_empty());
+
+        let mut half = Vec::new();
+        AlphaMask::new(lamp(), SolidColor::gray(0.5)).collect_lights(&mut half);
+        assert_eq!(half.len(), 1);
+        assert!((half[0].power() - 0.5 * unmasked[0].power()).abs() < 1e-10);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::bababoi::{random_double, random_pair};
+use crate::color::luminance;
+use crate::ppm::{read_ppm, PpmImage};
+use crate::vec3::Vec3;
+use std::f64::consts::PI;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Shape of the camera's lens opening, which out-of-focus highlights take on.
+#[derive(Clone)]
+pub enum Aperture {
+    Circular,
+    /// Regular polygon formed by `blades` straight diaphragm blades, turned
+    /// by `rotation` degrees.
+    Polygon {
+        blades: u32,
+        rotation: f64,
+    },
+    /// Arbitrary shape given by an image, brighter pixels letting more light
+    /// through.
+    Mask(Arc<ApertureMask>),
+}
+
+impl Aperture {
+    /// Samples a point on the aperture, with coordinates in [-1, 1].
+    pub fn sample(&self) -> Vec3 {
+        match self {
+            Aperture::Circular => Self::sample_disk(),
+            Aperture::Polygon { blades, rotation } => {
+                Self::sample_polygon((*blades).max(3), rotation.to_radians())
+            }
+            Aperture::Mask(mask) => mask.sample(random_double(), random_double(), random_double()),
+        }
+    }
+
+    /// Concentric mapping of a pair of random numbers onto the unit disk,
+    /// which keeps stratified samples evenly spread.
+    fn sample_disk() -> Vec3 {
+        let (a, b) = random_pair();
+        let (x, y) = (2.0 * a - 1.0, 2.0 * b - 1.0);
+        if x == 0.0 && y == 0.0 {
+            return Vec3::zero();
+        }
+        let (r, theta) = if x.abs() > y.abs() {
+            (x, PI / 4.0 * (y / x))
+        } else {
+            (y, PI / 2.0 - PI / 4.0 * (x / y))
+        };
+        Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+    }
+
+    /// Picks one of the polygon's triangles around the center, which all
+    /// have the same area, then a uniform point inside it.
+    fn sample_polygon(blades: u32, rotation: f64) -> Vec3 {
+        let k = ((random_double() * blades as f64) as u32).min(blades - 1);
+        let corner = |i: u32| {
+            let angle = rotation + 2.0 * PI * i as f64 / blades as f64;
+            Vec3::new(angle.cos(), angle.sin(), 0.0)
+        };
+
+        let (mut a, mut b) = random_pair();
+        if a + b > 1.0 {
+            a = 1.0 - a;
+            b = 1.0 - b;
+        }
+        corner(k) * a + corner(k + 1) * b
+    }
+}
+
+/// Aperture shape from a grayscale image, sampled in proportion to the
+/// brightness of its pixels.
+pub struct ApertureMask {
+    width: usize,
+    height: usize,
+    /// Cumulative pixel weights, normalized to end at 1.
+    cdf: Vec<f64>,
+}
+
+impl ApertureMask {
+    /// Builds a mask from `width * height` weights given row by row from the
+    /// top. Returns `None` if every weight is zero.
+    pub fn new(width: usize, height: usize, weights: &[f64]) -> Option<Self> {
+        let mut cdf = Vec::with_capacity(width * height);
+        let mut total = 0.0;
+        for &w in weights.iter().take(width * height) {
+            total += w.max(0.0);
+            cdf.push(total);
+        }
+        if total <= 0.0 || cdf.len() < width * height {
+            return None;
+        }
+        for c in cdf.iter_mut() {
+            *c /= total;
+        }
+        Some(Self { width, height, cdf })
+    }
+
+    /// Loads a mask from a PPM or PGM image, using the luminance of each
+    /// pixel as its weight.
+    pub fn from_ppm<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_image(&read_ppm(path)?)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "aperture mask is black"))
+    }
+
+    pub fn from_image(image: &PpmImage) -> Option<Self> {
+        let weights: Vec<f64> = image.pixels.iter().map(|&c| luminance(c)).collect();
+        Self::new(image.width, image.height, &weights)
+    }
+
+    /// Maps three uniform numbers to a point on the mask. The image is
+    /// centered on the lens with its longer side spanning [-1, 1], top up.
+    pub fn sample(&self, u_pixel: f64, u_x: f64, u_y: f64) -> Vec3 {
+        let index = self
+            .cdf
+            .partition_point(|&c| c <= u_pixel)
+            .min(self.cdf.len() - 1);
+        let x = (index % self.width) as f64 + u_x;
+        let y = (index / self.width) as f64 + u_y;
+        let size = self.width.max(self.height) as f64;
+        Vec3::new(
+            (2.0 * x - self.width as f64) / size,
+            (self.height as f64 - 2.0 * y) / size,
+            0.0,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_polygon_samples_stay_inside() {
+        let aperture = Aperture::Polygon {
+            blades: 5,
+            rotation: 18.0,
+        };
+        // Inscribed radius of a regular pentagon with unit corners.
+        let apothem = (PI / 5.0).cos();
+        let mut outside_apothem = 0;
+        for _ in 0..5000 {
+            let p = aperture.sample();
+            assert!(p.length() <= 1.0 + 1e-12);
+            if p.length() > apothem {
+                outside_apothem += 1;
+            }
+        }
+        assert!(outside_apothem > 0);
+    }
+
+    #[test]
+    fn test_mask_samples_only_bright_pixels() {
+        // 2 x 2 mask where only the top right pixel is open.
+        let mask = ApertureMask::new(2, 2, &[0.0, 1.0, 0.0, 0.0]).unwrap();
+        for i in 0..100 {
+            let u = i as f64 / 100.0;
+            let p = mask.sample(u, 0.5, 0.3);
+            assert!(p.x() > 0.0 && p.y() > 0.0);
+        }
+        assert!(ApertureMask::new(2, 2, &[0.0; 4]).is_none());
+
+        // Wide masks keep their proportions.
+        let wide = ApertureMask::new(4, 2, &[1.0; 8]).unwrap();
+        let corner = wide.sample(0.999, 0.999, 0.999);
+        assert!(corner.x() > 0.99 && corner.y() > -0.51 && corner.y() < -0.49);
+    }
+}
@@ -1,17 +1,69 @@
+use crate::sampler::Sampler;
 use rand::Rng;
+use std::cell::RefCell;
+
+thread_local! {
+    /// Sampler that `random_double` draws from while one is installed.
+    static SAMPLER: RefCell<Option<Box<dyn Sampler>>> = RefCell::new(None);
+}
 
 pub fn degrees_to_radians(degrees: f64) -> f64 {
     degrees * std::f64::consts::PI / 180.0
 }
 
 pub fn random_double() -> f64 {
-    rand::thread_rng().gen()
+    SAMPLER.with(|sampler| match sampler.borrow_mut().as_mut() {
+        Some(sampler) => sampler.get_1d(),
+        None => rand::thread_rng().gen(),
+    })
+}
+
+/// Two random numbers meant to be used together, such as a point on a disk,
+/// which a sampler can stratify jointly.
+pub fn random_pair() -> (f64, f64) {
+    SAMPLER.with(|sampler| match sampler.borrow_mut().as_mut() {
+        Some(sampler) => sampler.get_2d(),
+        None => (rand::thread_rng().gen(), rand::thread_rng().gen()),
+    })
+}
+
+/// Runs `f` with `sampler` supplying every random number drawn on this
+/// thread, so materials, lights and media use it without being handed it.
+pub fn with_sampler<R>(sampler: Box<dyn Sampler>, f: impl FnOnce() -> R) -> R {
+    let previous = SAMPLER.with(|s| s.borrow_mut().replace(sampler));
+    let result = f();
+    SAMPLER.with(|s| *s.borrow_mut() = previous);
+    result
+}
+
+/// Moves the installed sampler to sample `index` of pixel `(x, y)`.
+pub fn start_pixel_sample(x: i32, y: i32, index: u32) {
+    SAMPLER.with(|sampler| {
+        if let Some(sampler) = sampler.borrow_mut().as_mut() {
+            sampler.start_pixel_sample(x, y, index);
+        }
+    });
 }
 
 pub fn random_double_range(min: f64, max: f64) -> f64 {
     min + (max - min) * random_double()
 }
 
+/// Deterministic pseudo-random value in [0, 1) derived from `values`, for
+/// decisions that must come out the same every time a point is queried.
+pub fn hash_to_unit(values: &[f64]) -> f64 {
+    let mut h: u64 = 0x9e37_79b9_7f4a_7c15;
+    for v in values {
+        // SplitMix64 finalizer over each value's bits.
+        h ^= v.to_bits();
+        h = h.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        h ^= h >> 31;
+    }
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -20,7 +72,7 @@ mod tests {
     fn test_random_double_range() {
         for _ in 0..1000 {
             let r = random_double_range(5.0, 10.0);
-            assert!(r >= 5.0 && r < 10.0);
+            assert!((5.0..10.0).contains(&r));
         }
     }
 
@@ -29,4 +81,33 @@ mod tests {
         assert!((degrees_to_radians(180.0) - std::f64::consts::PI).abs() < 1e-10);
         assert!((degrees_to_radians(360.0) - 2.0 * std::f64::consts::PI).abs() < 1e-10);
     }
+
+    #[test]
+    fn test_with_sampler_routes_draws() {
+        struct Constant;
+        impl Sampler for Constant {
+            fn start_pixel_sample(&mut self, _x: i32, _y: i32, _index: u32) {}
+            fn get_1d(&mut self) -> f64 {
+                0.25
+            }
+            fn get_2d(&mut self) -> (f64, f64) {
+                (0.5, 0.75)
+            }
+        }
+
+        let drawn = with_sampler(Box::new(Constant), || (random_double(), random_pair()));
+        assert_eq!(drawn, (0.25, (0.5, 0.75)));
+        assert_ne!(random_double(), 0.25);
+    }
+
+    #[test]
+    fn test_hash_to_unit() {
+        let a = hash_to_unit(&[0.1, 0.2, 0.3]);
+        assert_eq!(a, hash_to_unit(&[0.1, 0.2, 0.3]));
+        assert_ne!(a, hash_to_unit(&[0.1, 0.2, 0.30001]));
+
+        let n = 10000;
+        let mean = (0..n).map(|i| hash_to_unit(&[i as f64])).sum::<f64>() / n as f64;
+        assert!((mean - 0.5).abs() < 0.02);
+    }
 }
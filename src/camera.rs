@@ -1,17 +1,128 @@
-use crate::bababoi::{degrees_to_radians, random_double, random_double_range};
+use crate::adaptive::AdaptiveSampling;
+use crate::aperture::Aperture;
+use crate::bababoi::{self, degrees_to_radians, random_double, random_pair};
+use crate::checkpoint::{Checkpoint, Checkpointing};
 use crate::color::write_color;
+use crate::film::Film;
+use crate::filter::Filter;
 use crate::hittable::{HitRecord, Hittable};
+use crate::lens::LensSystem;
+use crate::light::{LightSampler, LightSampling, SunLight};
 use crate::material::Lambertian;
+use crate::medium::{Medium, MediumEvent};
+use crate::progressive::Progressive;
 use crate::ray::Ray;
+use crate::sampler::SamplerKind;
+use crate::sky::Sky;
+use crate::spectrum::SampledWavelengths;
 use crate::vec3::{Color, Point3, Vec3};
+use std::f64::consts::PI;
+use std::fs;
 use std::io;
 use std::io::Write;
 use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::fs::File;
 
 #[cfg(feature = "gpu")]
 use crate::gpu;
 
+/// Scattering events inside media along one path before Russian roulette
+/// starts ending the walk. Random walks through nearly white media then still
+/// end, without darkening them the way a hard cut would.
+const WALK_ROULETTE_START: i32 = 128;
+/// Highest chance of a walk surviving each step once roulette has started.
+const WALK_MAX_SURVIVAL: f64 = 0.995;
+
+/// How the camera maps image positions to rays.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// Perspective with vertical field of view `vfov`, optionally through a
+    /// thin lens for depth of field.
+    Perspective,
+    /// Parallel rays along the view direction, seeing an area `view_height`
+    /// scene units tall. Depth of field does not apply.
+    Orthographic { view_height: f64 },
+    /// Full 360 by 180 degree latitude-longitude panorama, best rendered at
+    /// an aspect ratio of 2.
+    Equirectangular,
+    /// Equidistant fisheye covering `fov` degrees across the image circle,
+    /// which fits the shorter side of the image.
+    Fisheye { fov: f64 },
+}
+
+/// Camera body and lens settings, as an alternative to setting `vfov`,
+/// `defocus_angle` and exposure directly. Lengths are in millimeters and
+/// scene units are taken to be meters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhysicalCamera {
+    /// Width of the sensor; its height follows from the image aspect ratio.
+    pub sensor_width: f64,
+    pub focal_length: f64,
+    pub f_number: f64,
+    /// Exposure time in seconds.
+    pub shutter_speed: f64,
+    pub iso: f64,
+}
+
+impl Default for PhysicalCamera {
+    /// A full-frame camera with a 50mm lens at f/2.8, 1/125s and ISO 100.
+    fn default() -> Self {
+        Self {
+            sensor_width: 36.0,
+            focal_length: 50.0,
+            f_number: 2.8,
+            shutter_speed: 1.0 / 125.0,
+            iso: 100.0,
+        }
+    }
+}
+
+impl PhysicalCamera {
+    /// Vertical field of view in degrees.
+    pub fn vfov(&self, aspect_ratio: f64) -> f64 {
+        let sensor_height = self.sensor_width / aspect_ratio;
+        2.0 * (sensor_height / (2.0 * self.focal_length)).atan().to_degrees()
+    }
+
+    /// Radius of the lens opening in scene units.
+    pub fn aperture_radius(&self) -> f64 {
+        self.focal_length / self.f_number / 2.0 / 1000.0
+    }
+
+    /// Exposure value at ISO 100 for these settings.
+    pub fn ev100(&self) -> f64 {
+        (self.f_number * self.f_number / self.shutter_speed * 100.0 / self.iso).log2()
+    }
+
+    /// Factor turning scene radiance in cd/m^2 into pixel values, using the
+    /// saturation-based sensitivity of ISO 12232 (as in Lagarde and de
+    /// Rousiers 2014).
+    pub fn exposure(&self) -> f64 {
+        1.0 / (1.2 * 2f64.powf(self.ev100()))
+    }
+}
+
+/// One viewpoint of a multi-view render, replacing the camera's position,
+/// target and lens shift.
+#[derive(Debug, Clone)]
+pub struct View {
+    pub name: String,
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub lens_shift: (f64, f64),
+}
+
+/// How `Camera::render_views` writes its images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewLayout {
+    /// All views next to each other in one image, left to right.
+    SideBySide,
+    /// One file per view, named after the output file and the view.
+    Separate,
+}
+
 pub struct Camera {
     pub aspect_ratio: f64,
     pub image_width: i32,
@@ -24,6 +135,41 @@ pub struct Camera {
     pub defocus_angle: f64,
     pub focus_dist: f64,
     pub use_gpu: bool,
+    pub sky: Option<Sky>,
+    pub light_sampling: LightSampling,
+    pub spectral: bool,
+    pub projection: Projection,
+    pub aperture: Aperture,
+    /// When set, overrides `vfov` and `defocus_angle` and scales the image
+    /// by the camera's exposure.
+    pub physical: Option<PhysicalCamera>,
+    /// When set, rays are traced through this lens instead of using the
+    /// projection. It is focused at `focus_dist` meters, and the film is the
+    /// physical camera's sensor or else 36mm wide. Brightness is relative:
+    /// the lens adds vignetting and falloff towards the corners, but its
+    /// f-number doesn't change the exposure, which still comes from the
+    /// physical camera if one is set.
+    pub lens: Option<Arc<LensSystem>>,
+    /// Offset of the image window, as fractions of its width and height,
+    /// without turning the camera.
+    pub lens_shift: (f64, f64),
+    /// How the random numbers of each pixel's samples are spread out.
+    pub sampler: SamplerKind,
+    /// Makes CPU renders repeat exactly: every sample of every pixel draws
+    /// its random numbers from a stream derived from this seed.
+    pub seed: Option<u64>,
+    /// How samples are weighted into the pixels around them.
+    pub filter: Filter,
+    /// Varies the samples per pixel with how noisy each pixel is, replacing
+    /// `samples_per_pixel`.
+    pub adaptive: Option<AdaptiveSampling>,
+    /// Renders to a file in passes until a time or noise limit instead of a
+    /// fixed number of samples. Only used by `render_to_file`.
+    pub progressive: Option<Progressive>,
+    /// Saves a checkpoint after every pass of a render to a file, so it can
+    /// be picked up with `resume_to_file` if it stops. Implies rendering in
+    /// passes.
+    pub checkpoint: Option<Checkpointing>,
 
     image_height: i32,
     center: Point3,
@@ -35,11 +181,22 @@ pub struct Camera {
     w: Vec3,
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
+    defocus_radius: f64,
+    exposure: f64,
+    focused_lens: Option<LensSystem>,
+    sun: Option<SunLight>,
+    film_size: (f64, f64),
     viewport_lower_left: Point3,
     viewport_horizontal: Vec3,
     viewport_vertical: Vec3,
 }
 
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Camera {
     pub fn new() -> Self {
         Self {
@@ -54,6 +211,20 @@ impl Camera {
             defocus_angle: 0.0,
             focus_dist: 10.0,
             use_gpu: false,
+            sky: None,
+            light_sampling: LightSampling::Bvh,
+            spectral: false,
+            projection: Projection::Perspective,
+            aperture: Aperture::Circular,
+            physical: None,
+            lens: None,
+            lens_shift: (0.0, 0.0),
+            sampler: SamplerKind::Independent,
+            seed: None,
+            filter: Filter::default(),
+            adaptive: None,
+            progressive: None,
+            checkpoint: None,
             image_height: 0,
             center: Point3::zero(),
             pixel00_loc: Point3::zero(),
@@ -64,6 +235,11 @@ impl Camera {
             w: Vec3::zero(),
             defocus_disk_u: Vec3::zero(),
             defocus_disk_v: Vec3::zero(),
+            defocus_radius: 0.0,
+            exposure: 1.0,
+            focused_lens: None,
+            sun: None,
+            film_size: (0.0, 0.0),
             viewport_lower_left: Point3::zero(),
             viewport_horizontal: Vec3::zero(),
             viewport_vertical: Vec3::zero(),
@@ -71,18 +247,19 @@ impl Camera {
     }
 
     fn initialize(&mut self) {
-        self.image_height = (self.image_width as f64 / self.aspect_ratio) as i32;
-        self.image_height = if self.image_height < 1 {
-            1
-        } else {
-            self.image_height
-        };
+        self.image_height = self.rounded_image_height();
 
         self.center = self.lookfrom;
 
-        let theta = self.vfov.to_radians();
-        let h = (theta / 2.0).tan();
-        let viewport_height = 2.0 * h * self.focus_dist;
+        // Panoramic projections don't use the viewport, but keep it valid.
+        let viewport_height = match self.projection {
+            Projection::Perspective | Projection::Equirectangular | Projection::Fisheye { .. } => {
+                let theta = self.effective_vfov().to_radians();
+                let h = (theta / 2.0).tan();
+                2.0 * h * self.focus_dist
+            }
+            Projection::Orthographic { view_height } => view_height,
+        };
         let viewport_width = viewport_height * (self.image_width as f64 / self.image_height as f64);
 
         self.w = (self.lookfrom - self.lookat).unit_vector();
@@ -98,48 +275,119 @@ impl Camera {
         self.pixel_delta_u = viewport_u / self.image_width as f64;
         self.pixel_delta_v = viewport_v / self.image_height as f64;
 
-        let viewport_upper_left =
-            self.center - (self.w * self.focus_dist) - viewport_u / 2.0 - viewport_v / 2.0;
+        let shift = self.u * (viewport_width * self.lens_shift.0)
+            + self.v * (viewport_height * self.lens_shift.1);
+        let viewport_upper_left = self.center - (self.w * self.focus_dist) + shift
+            - viewport_u / 2.0
+            - viewport_v / 2.0;
         self.pixel00_loc = viewport_upper_left + (self.pixel_delta_u + self.pixel_delta_v) * 0.5;
             
         // Store viewport lower left corner for GPU rendering
-        self.viewport_lower_left = self.center - (self.w * self.focus_dist) 
+        self.viewport_lower_left = self.center - (self.w * self.focus_dist) + shift
             - (self.viewport_horizontal / 2.0) 
             - (self.viewport_vertical / 2.0);
 
         // Calculate the camera defocus disk basis vectors
-        let defocus_radius = self.focus_dist * (degrees_to_radians(self.defocus_angle / 2.0)).tan();
-        self.defocus_disk_u = self.u * defocus_radius;
-        self.defocus_disk_v = self.v * defocus_radius;
-    }
+        self.defocus_radius = match &self.physical {
+            Some(physical) => physical.aperture_radius(),
+            None => self.focus_dist * (degrees_to_radians(self.defocus_angle / 2.0)).tan(),
+        };
+        self.defocus_disk_u = self.u * self.defocus_radius;
+        self.defocus_disk_v = self.v * self.defocus_radius;
 
-    fn random_in_unit_disk() -> Vec3 {
-        loop {
-            let p = Vec3::new(
-                random_double_range(-1.0, 1.0),
-                random_double_range(-1.0, 1.0),
-                0.0,
-            );
-            if p.length_squared() < 1.0 {
-                return p;
+        self.exposure = self.physical.map_or(1.0, |physical| physical.exposure());
+        self.sun = self.sky.as_ref().map(Sky::sun_light);
+
+        let film_width = self.physical.map_or(36.0, |physical| physical.sensor_width) * 0.001;
+        self.film_size = (film_width, film_width / self.image_aspect());
+        self.focused_lens = self.lens.as_ref().map(|lens| match lens.focus(self.focus_dist) {
+            Some(film_distance) => lens.with_film_distance(film_distance),
+            None => {
+                eprintln!("Lens cannot focus at {}; using it as given.", self.focus_dist);
+                lens.as_ref().clone()
             }
+        });
+    }
+
+    /// Vertical field of view in degrees, from the physical camera if set.
+    fn effective_vfov(&self) -> f64 {
+        match &self.physical {
+            Some(physical) => physical.vfov(self.image_aspect()),
+            None => self.vfov,
+        }
+    }
+
+    /// Image height for `image_width` and `aspect_ratio`, at least one pixel.
+    fn rounded_image_height(&self) -> i32 {
+        ((self.image_width as f64 / self.aspect_ratio) as i32).max(1)
+    }
+
+    /// Width over height of the image in whole pixels, which can differ a
+    /// little from `aspect_ratio` after rounding the height.
+    fn image_aspect(&self) -> f64 {
+        self.image_width as f64 / self.rounded_image_height() as f64
+    }
+
+    fn build_lights(&self, world: &dyn Hittable) -> Box<dyn LightSampler> {
+        let mut lights = Vec::new();
+        world.collect_lights(&mut lights);
+        if !lights.is_empty() {
+            eprintln!("Lights: {}", lights.len());
         }
+        self.light_sampling.build(lights)
     }
 
     fn defocus_disk_sample(&self) -> Point3 {
-        let p = Self::random_in_unit_disk();
+        let p = self.aperture.sample();
         self.center + (self.defocus_disk_u * p.x()) + (self.defocus_disk_v * p.y())
     }
 
-    fn get_ray(&self, i: i32, j: i32) -> Ray {
+    /// Ray through pixel `(i, j)` at an offset from its center, or `None`
+    /// where the projection leaves the image empty, outside a fisheye's image
+    /// circle.
+    fn get_ray(&self, i: i32, j: i32, offset_u: f64, offset_v: f64) -> Option<Ray> {
+        match self.projection {
+            Projection::Equirectangular => {
+                let (s, t) = self.image_coordinates(i, j, offset_u, offset_v);
+                let longitude = (s - 0.5) * 2.0 * PI;
+                let latitude = (0.5 - t) * PI;
+                let direction = self.u * (latitude.cos() * longitude.sin())
+                    - self.w * (latitude.cos() * longitude.cos())
+                    + self.v * latitude.sin();
+                return Some(Ray::new(self.center, direction));
+            }
+            Projection::Fisheye { fov } => {
+                let (s, t) = self.image_coordinates(i, j, offset_u, offset_v);
+                // Equidistant mapping: the angle from the view direction
+                // grows linearly with distance from the image center.
+                let diameter = self.image_width.min(self.image_height) as f64;
+                let x = (s - 0.5) * self.image_width as f64 / (diameter / 2.0);
+                let y = (0.5 - t) * self.image_height as f64 / (diameter / 2.0);
+                let r = (x * x + y * y).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+                let theta = r * fov.to_radians() / 2.0;
+                let phi = y.atan2(x);
+                let direction = (self.u * phi.cos() + self.v * phi.sin()) * theta.sin()
+                    - self.w * theta.cos();
+                return Some(Ray::new(self.center, direction));
+            }
+            _ => {}
+        }
+
         let pixel_center =
             self.pixel00_loc + (self.pixel_delta_u * i as f64) + (self.pixel_delta_v * j as f64);
 
-        let pixel_sample = pixel_center
-            + self.pixel_delta_u * (random_double() - 0.5)
-            + self.pixel_delta_v * (random_double() - 0.5);
+        let pixel_sample =
+            pixel_center + self.pixel_delta_u * offset_u + self.pixel_delta_v * offset_v;
 
-        let ray_origin = if self.defocus_angle <= 0.0 {
+        if let Projection::Orthographic { .. } = self.projection {
+            // Move the sample back onto the plane through the camera center.
+            return Some(Ray::new(pixel_sample + self.w * self.focus_dist, -self.w));
+        }
+
+        let ray_origin = if self.defocus_radius <= 0.0 {
             self.center
         } else {
             self.defocus_disk_sample()
@@ -147,26 +395,55 @@ impl Camera {
 
         let ray_direction = pixel_sample - ray_origin;
 
-        Ray::new(ray_origin, ray_direction)
+        Some(Ray::new(ray_origin, ray_direction))
     }
 
-    fn ray_color(&self, ray: &Ray, depth: i32, world: &dyn Hittable) -> Color {
-        if depth <= 0 {
-            return Color::zero();
-        }
+    /// Ray through pixel `(i, j)` at an offset from its center traced out
+    /// through the lens, with its weight for the cos^4 falloff of light
+    /// reaching the film. Returns `None` when the lens blocks the ray.
+    ///
+    /// The weight leaves out the pupil area over the squared film distance
+    /// that would make it absolute, so a ray along the axis keeps weight 1.
+    fn lens_ray(
+        &self,
+        lens: &LensSystem,
+        i: i32,
+        j: i32,
+        offset_u: f64,
+        offset_v: f64,
+    ) -> Option<(Ray, f64)> {
+        let (s, t) = self.image_coordinates(i, j, offset_u, offset_v);
+        // The lens flips the image, so the film is read upside down.
+        let (film_width, film_height) = self.film_size;
+        let film = Point3::new((0.5 - s) * film_width, (t - 0.5) * film_height, 0.0);
 
-        let mut rec = HitRecord::new(
-            Point3::zero(),
-            Vec3::zero(),
-            Lambertian::new(Color::zero()),
-            0.0,
+        let pupil = Aperture::Circular.sample() * lens.rear_aperture_radius();
+        let rear = Point3::new(pupil.x(), pupil.y(), lens.rear_z());
+        let direction = (rear - film).unit_vector();
+        let weight = direction.z().powi(4);
+
+        let out = lens.trace_from_film(&Ray::new(film, direction))?;
+        let to_world = |v: Vec3| self.u * v.x() + self.v * v.y() - self.w * v.z();
+        let ray = Ray::new(
+            self.center + to_world(out.origin()),
+            to_world(out.direction()),
         );
+        Some((ray, weight))
+    }
 
-        if world.hit(ray, 0.001, f64::INFINITY, &mut rec) {
-            if let Some((attenuation, scattered)) = rec.mat.scatter(ray, &rec) {
-                return attenuation * self.ray_color(&scattered, depth - 1, world);
-            }
-            return Color::zero();
+    /// Position of a sample in pixel `(i, j)` as fractions of the image width
+    /// and height, measured from the top left. The lens shift moves the image
+    /// window the same way it moves the perspective viewport.
+    fn image_coordinates(&self, i: i32, j: i32, offset_u: f64, offset_v: f64) -> (f64, f64) {
+        (
+            (i as f64 + 0.5 + offset_u) / self.image_width as f64 + self.lens_shift.0,
+            (j as f64 + 0.5 + offset_v) / self.image_height as f64 - self.lens_shift.1,
+        )
+    }
+
+    fn background(&self, ray: &Ray) -> Color {
+        if let Some(sky) = &self.sky {
+            return sky.radiance(&ray.direction());
         }
 
         let unit_direction = ray.direction().unit_vector();
@@ -174,6 +451,285 @@ impl Camera {
         Color::new(1.0, 1.0, 1.0) * (1.0 - a) + Color::new(0.5, 0.7, 1.0) * a
     }
 
+    /// Estimates one sample of pixel `(i, j)` as linear RGB, along with the
+    /// sample's offset from the pixel center.
+    fn sample_pixel(
+        &self,
+        i: i32,
+        j: i32,
+        world: &dyn Hittable,
+        lights: &dyn LightSampler,
+    ) -> (Color, (f64, f64)) {
+        let (offset_u, offset_v) = random_pair();
+        let offset = (offset_u - 0.5, offset_v - 0.5);
+        let sample = match &self.focused_lens {
+            Some(lens) => self.lens_ray(lens, i, j, offset.0, offset.1),
+            None => self.get_ray(i, j, offset.0, offset.1).map(|ray| (ray, 1.0)),
+        };
+        let (ray, weight) = match sample {
+            Some(sample) => sample,
+            None => return (Color::zero(), offset),
+        };
+        let radiance = if self.spectral {
+            let mut wavelengths = SampledWavelengths::sample_visible(random_double());
+            let values = self.ray_color(&ray, world, lights, Some(&mut wavelengths));
+            wavelengths.to_rgb(values)
+        } else {
+            self.ray_color(&ray, world, lights, None)
+        };
+        (radiance * (weight * self.exposure), offset)
+    }
+
+    /// Traces a path and returns the radiance it carries back. For spectral
+    /// paths each channel holds the value at one of the sampled wavelengths,
+    /// and every RGB quantity met along the way is upsampled to them.
+    fn ray_color(
+        &self,
+        ray: &Ray,
+        world: &dyn Hittable,
+        lights: &dyn LightSampler,
+        mut wavelengths: Option<&mut SampledWavelengths>,
+    ) -> Color {
+        let hero = wavelengths.as_deref().map(|w| w.hero());
+        let mut radiance = Color::zero();
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut ray = ray.with_wavelength(hero);
+        // Position, normal and BSDF density of the previous bounce, for
+        // weighting emission found by BSDF sampling against light sampling.
+        let mut prev_bounce: Option<(Point3, Vec3, f64)> = None;
+        // Media the path is currently inside, innermost last.
+        let mut media: Vec<Medium> = Vec::new();
+
+        let mut depth = 0;
+        let mut walk_steps = 0;
+
+        while depth < self.max_depth {
+            let mut rec = HitRecord::new(
+                Point3::zero(),
+                Vec3::zero(),
+                Lambertian::new(Color::zero()),
+                0.0,
+            );
+            let hit = world.hit(&ray, 0.001, f64::INFINITY, &mut rec);
+
+            if let Some(medium) = media.last() {
+                let length = ray.direction().length();
+                let t_max = if hit { rec.t * length } else { f64::INFINITY };
+
+                if medium.is_scattering() {
+                    let medium = lift_medium(wavelengths.as_deref(), medium);
+                    match medium.sample_interaction(t_max, random_double(), random_double()) {
+                        MediumEvent::Scatter { distance, weight } => {
+                            // Random walk step inside the medium. These don't
+                            // count as bounces, or dense media would go dark.
+                            throughput = throughput * weight;
+                            walk_steps += 1;
+                            if throughput.near_zero() {
+                                break;
+                            }
+                            if walk_steps > WALK_ROULETTE_START {
+                                // Survivors carry the share of the walks that
+                                // stopped, so the estimate stays unbiased.
+                                let survival = throughput
+                                    .x()
+                                    .max(throughput.y())
+                                    .max(throughput.z())
+                                    .min(WALK_MAX_SURVIVAL);
+                                if random_double() >= survival {
+                                    break;
+                                }
+                                throughput /= survival;
+                            }
+                            let direction = ray.direction() / length;
+                            let scattered = medium.sample_phase(
+                                &direction,
+                                random_double(),
+                                random_double(),
+                            );
+                            ray = Ray::new(ray.origin() + direction * distance, scattered)
+                                .with_wavelength(hero);
+                            prev_bounce = None;
+                            continue;
+                        }
+                        MediumEvent::Pass { weight } => throughput = throughput * weight,
+                    }
+                } else if hit {
+                    let transmittance = medium.transmittance(t_max);
+                    throughput = throughput * lift(wavelengths.as_deref(), transmittance);
+                }
+            }
+
+            if !hit {
+                let background = match (&self.sky, &self.sun, prev_bounce) {
+                    // The sun was also sampled directly at the last bounce.
+                    (Some(sky), Some(sun), Some((_, _, bsdf_pdf))) if bsdf_pdf > 0.0 => {
+                        let direction = ray.direction();
+                        let weight = power_heuristic(bsdf_pdf, sun.pdf(&direction));
+                        sky.sky_radiance(&direction) + sun.radiance(&direction) * weight
+                    }
+                    _ => self.background(&ray),
+                };
+                radiance += throughput * lift(wavelengths.as_deref(), background);
+                break;
+            }
+
+            let emitted = lift(wavelengths.as_deref(), rec.mat.emitted(&ray, &rec));
+            if !emitted.near_zero() {
+                let weight = match prev_bounce {
+                    Some((p, n, bsdf_pdf)) if bsdf_pdf > 0.0 => {
+                        power_heuristic(bsdf_pdf, lights.pdf(p, n, &ray.direction()))
+                    }
+                    _ => 1.0,
+                };
+                radiance += throughput * emitted * weight;
+            }
+
+            if !lights.is_empty() && !rec.mat.is_delta() {
+                let direct = self.sample_direct(
+                    &ray,
+                    &rec,
+                    world,
+                    lights,
+                    &media,
+                    wavelengths.as_deref(),
+                );
+                radiance += throughput * direct;
+            }
+
+            if let Some(sun) = &self.sun {
+                if !rec.mat.is_delta() {
+                    let direct =
+                        self.sample_sun(sun, &ray, &rec, world, &media, wavelengths.as_deref());
+                    radiance += throughput * direct;
+                }
+            }
+
+            let (attenuation, scattered, delta) = match rec.mat.scatter_lobe(&ray, &rec) {
+                Some(s) => s,
+                None => break,
+            };
+
+            if rec.mat.is_dispersive() {
+                if let Some(wavelengths) = wavelengths.as_deref_mut() {
+                    wavelengths.terminate_secondary();
+                }
+            }
+
+            if let Some(medium) = rec.mat.interior_medium() {
+                // Crossing the surface enters or leaves the object's interior.
+                if scattered.direction().dot(&rec.normal) < 0.0 {
+                    if rec.front_face {
+                        media.push(medium);
+                    } else if let Some(index) = media.iter().rposition(|m| *m == medium) {
+                        // Media can overlap, so this one needn't be innermost.
+                        media.remove(index);
+                    }
+                }
+            }
+
+            // Light sampling can't find directions from a delta lobe, so what
+            // they hit counts in full.
+            let bsdf_pdf = if delta {
+                0.0
+            } else {
+                rec.mat.pdf(&ray, &rec, &scattered.direction())
+            };
+            prev_bounce = Some((rec.p, rec.normal, bsdf_pdf));
+            throughput = throughput * lift(wavelengths.as_deref(), attenuation);
+            ray = scattered.with_wavelength(hero);
+            depth += 1;
+        }
+
+        radiance
+    }
+
+    /// Next event estimation: samples one light and returns its direct
+    /// contribution at `rec`, weighted by multiple importance sampling.
+    fn sample_direct(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        world: &dyn Hittable,
+        lights: &dyn LightSampler,
+        media: &[Medium],
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> Color {
+        let direction = match lights.sample(rec.p, rec.normal) {
+            Some(d) => d,
+            None => return Color::zero(),
+        };
+
+        let f = rec.mat.eval(r_in, rec, &direction);
+        if f.near_zero() {
+            return Color::zero();
+        }
+
+        let light_pdf = lights.pdf(rec.p, rec.normal, &direction);
+        if light_pdf <= 0.0 {
+            return Color::zero();
+        }
+
+        let shadow_ray = Ray::new(rec.p, direction).with_wavelength(r_in.wavelength());
+        let mut light_rec = rec.clone();
+        if !world.hit(&shadow_ray, 0.001, f64::INFINITY, &mut light_rec) {
+            return Color::zero();
+        }
+
+        let emitted = light_rec.mat.emitted(&shadow_ray, &light_rec);
+        if emitted.near_zero() {
+            return Color::zero();
+        }
+
+        let transmittance = match medium_towards(media, rec, &direction) {
+            Some(medium) => medium.transmittance(light_rec.t * direction.length()),
+            None => Color::new(1.0, 1.0, 1.0),
+        };
+
+        let bsdf_pdf = rec.mat.pdf(r_in, rec, &direction);
+        let contribution = lift(wavelengths, f) * lift(wavelengths, emitted * transmittance);
+        contribution * (power_heuristic(light_pdf, bsdf_pdf) / light_pdf)
+    }
+
+    /// Next event estimation for the sun: samples its disk and returns the
+    /// direct contribution at `rec` if the way to the sky is clear, weighted
+    /// by multiple importance sampling.
+    fn sample_sun(
+        &self,
+        sun: &SunLight,
+        r_in: &Ray,
+        rec: &HitRecord,
+        world: &dyn Hittable,
+        media: &[Medium],
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> Color {
+        let direction = sun.sample();
+        let emitted = sun.radiance(&direction);
+        if emitted.near_zero() {
+            return Color::zero();
+        }
+        // Media fill closed objects, so a ray still inside one can't be
+        // reaching the sky.
+        if medium_towards(media, rec, &direction).is_some() {
+            return Color::zero();
+        }
+
+        let f = rec.mat.eval(r_in, rec, &direction);
+        if f.near_zero() {
+            return Color::zero();
+        }
+
+        let shadow_ray = Ray::new(rec.p, direction).with_wavelength(r_in.wavelength());
+        let mut shadow_rec = rec.clone();
+        if world.hit(&shadow_ray, 0.001, f64::INFINITY, &mut shadow_rec) {
+            return Color::zero();
+        }
+
+        let sun_pdf = sun.pdf(&direction);
+        let bsdf_pdf = rec.mat.pdf(r_in, rec, &direction);
+        let contribution = lift(wavelengths, f) * lift(wavelengths, emitted);
+        contribution * (power_heuristic(sun_pdf, bsdf_pdf) / sun_pdf)
+    }
+
     #[cfg(feature = "gpu")]
     async fn render_gpu(&mut self, world: &dyn Hittable) -> io::Result<()> {
         self.initialize();
@@ -211,12 +767,6 @@ impl Camera {
         Ok(())
     }
     
-    #[cfg(not(feature = "gpu"))]
-    async fn render_gpu(&mut self, _world: &dyn Hittable) -> io::Result<()> {
-        eprintln!("GPU rendering is not enabled. Compile with --features gpu to enable.");
-        Ok(())
-    }
-
     pub fn render(&mut self, world: &dyn Hittable) -> io::Result<()> {
         if self.use_gpu {
             #[cfg(feature = "gpu")]
@@ -234,23 +784,14 @@ impl Camera {
         
         // Standard CPU rendering
         self.initialize();
+        let lights = self.build_lights(world);
 
         println!("P3");
         println!("{} {}", self.image_width, self.image_height);
         println!("255");
 
-        for j in 0..self.image_height {
-            eprint!("\rScanlines remaining: {} ", self.image_height - j);
-            for i in 0..self.image_width {
-                let mut pixel_color = Color::zero();
-
-                for _ in 0..self.samples_per_pixel {
-                    let ray = self.get_ray(i, j);
-                    pixel_color += self.ray_color(&ray, self.max_depth, world);
-                }
-
-                write_color(&mut io::stdout(), pixel_color, self.samples_per_pixel)?;
-            }
+        for pixel_color in self.render_film(world, lights.as_ref()).pixels() {
+            write_color(&mut io::stdout(), pixel_color, 1)?;
         }
 
         eprintln!("\nDone.");
@@ -259,31 +800,799 @@ impl Camera {
     
     pub fn render_to_file(&mut self, world: &dyn Hittable, filename: &str) -> io::Result<()> {
         self.initialize();
+        let lights = self.build_lights(world);
+        if self.checkpoint.is_some() && self.seed.is_none() {
+            // Resuming needs the same random numbers the first run would
+            // have drawn.
+            self.seed = Some((random_double() * (1u64 << 53) as f64) as u64);
+        }
+        let film = match self.pass_settings() {
+            Some(progressive) => {
+                let film = Film::new(
+                    self.image_width as usize,
+                    self.image_height as usize,
+                    self.filter,
+                );
+                self.render_progressive(world, lights.as_ref(), &progressive, filename, film, 0)?
+            }
+            None => {
+                let film = self.render_film(world, lights.as_ref());
+                self.write_ppm(filename, self.image_width, &film.pixels())?;
+                film
+            }
+        };
+        if self.adaptive.is_some_and(|adaptive| adaptive.write_heatmap) {
+            self.write_heatmap(&view_filename(filename, "samples"), &film)?;
+        }
 
-        // Create a file
-        let path = Path::new(filename);
-        let mut file = File::create(path)?;
+        eprintln!("\nDone.");
+        Ok(())
+    }
 
-        // Write PPM header
-        writeln!(file, "P3")?;
-        writeln!(file, "{} {}", self.image_width, self.image_height)?;
-        writeln!(file, "255")?;
+    /// Continues an interrupted render from its checkpoint, adding passes
+    /// until a limit of `progressive` is reached, or up to
+    /// `samples_per_pixel` without one, and writing to the render's output.
+    /// The camera must be set up as it was for the first run.
+    pub fn resume_to_file(&mut self, world: &dyn Hittable, checkpoint: Checkpoint) -> io::Result<()> {
+        self.initialize();
+        let film = checkpoint.film;
+        if (film.width(), film.height()) != (self.image_width as usize, self.image_height as usize) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "checkpoint is for a different image size",
+            ));
+        }
+        // Without any limit, finish at `samples_per_pixel` as the first run
+        // would have.
+        let progressive = self.pass_settings().unwrap_or(Progressive {
+            max_samples: Some(self.samples_per_pixel.max(1) as u32),
+            ..Progressive::default()
+        });
+        // The samples to come must continue the patterns of those taken.
+        let without_heatmap = |adaptive: Option<AdaptiveSampling>| {
+            adaptive.map(|adaptive| AdaptiveSampling {
+                write_heatmap: false,
+                ..adaptive
+            })
+        };
+        let mismatch = if self.sampler != checkpoint.sampler {
+            Some("a different sampler")
+        } else if without_heatmap(self.adaptive) != without_heatmap(checkpoint.adaptive) {
+            Some("different adaptive sampling settings")
+        } else if progressive.samples_per_pass != checkpoint.progressive.samples_per_pass {
+            Some("a different pass size")
+        } else if self.filter != film.filter() {
+            Some("a different pixel filter")
+        } else {
+            None
+        };
+        if let Some(setting) = mismatch {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("checkpoint was rendered with {setting}"),
+            ));
+        }
+        if progressive != checkpoint.progressive {
+            eprintln!("Resuming with different limits than the checkpoint's");
+        }
+        self.seed = Some(checkpoint.seed);
+        let lights = self.build_lights(world);
+
+        eprintln!("Resuming at {} samples per pixel", checkpoint.samples);
+        let filename = checkpoint.output;
+        let film = self.render_progressive(
+            world,
+            lights.as_ref(),
+            &progressive,
+            &filename,
+            film,
+            checkpoint.samples,
+        )?;
+        if self.adaptive.is_some_and(|adaptive| adaptive.write_heatmap) {
+            self.write_heatmap(&view_filename(&filename, "samples"), &film)?;
+        }
 
-        for j in 0..self.image_height {
-            eprint!("\rScanlines remaining: {} ", self.image_height - j);
-            for i in 0..self.image_width {
-                let mut pixel_color = Color::zero();
+        eprintln!("\nDone.");
+        Ok(())
+    }
 
-                for _ in 0..self.samples_per_pixel {
-                    let ray = self.get_ray(i, j);
-                    pixel_color += self.ray_color(&ray, self.max_depth, world);
+    /// Progressive settings to render with. Checkpointed renders always go
+    /// in passes, stopping at `samples_per_pixel` unless told otherwise.
+    fn pass_settings(&self) -> Option<Progressive> {
+        match (&self.progressive, &self.checkpoint) {
+            (Some(progressive), _) => Some(*progressive),
+            (None, Some(_)) => Some(Progressive {
+                max_samples: Some(self.samples_per_pixel.max(1) as u32),
+                ..Progressive::default()
+            }),
+            (None, None) => None,
+        }
+    }
+
+    /// Left and right eye views for stereo, `interpupillary_distance` apart
+    /// around the current position. The eyes look in parallel and a lens
+    /// shift puts objects at `convergence_distance` at screen depth, which
+    /// avoids the vertical parallax of toed-in cameras.
+    pub fn stereo_views(&self, interpupillary_distance: f64, convergence_distance: f64) -> Vec<View> {
+        let forward = self.lookat - self.lookfrom;
+        let right = forward.cross(&self.vup).unit_vector();
+
+        // Width of the image window per unit of distance from the eye.
+        let window = 2.0 * (self.effective_vfov().to_radians() / 2.0).tan() * self.image_aspect();
+
+        [("left", -0.5), ("right", 0.5)]
+            .into_iter()
+            .map(|(name, side)| {
+                let offset = side * interpupillary_distance;
+                let eye = self.lookfrom + right * offset;
+                View {
+                    name: name.to_string(),
+                    lookfrom: eye,
+                    lookat: eye + forward,
+                    lens_shift: (
+                        self.lens_shift.0 - offset / (convergence_distance * window),
+                        self.lens_shift.1,
+                    ),
                 }
+            })
+            .collect()
+    }
+
+    /// Renders each of `views` with the rest of this camera's settings,
+    /// building the lights once for all of them.
+    pub fn render_views(
+        &mut self,
+        world: &dyn Hittable,
+        views: &[View],
+        layout: ViewLayout,
+        filename: &str,
+    ) -> io::Result<()> {
+        let saved = (self.lookfrom, self.lookat, self.lens_shift);
+        let lights = self.build_lights(world);
 
-                write_color(&mut file, pixel_color, self.samples_per_pixel)?;
+        let mut images = Vec::with_capacity(views.len());
+        for (n, view) in views.iter().enumerate() {
+            eprintln!("View {} of {}: {}", n + 1, views.len(), view.name);
+            self.lookfrom = view.lookfrom;
+            self.lookat = view.lookat;
+            self.lens_shift = view.lens_shift;
+            self.initialize();
+            images.push(self.render_film(world, lights.as_ref()).pixels());
+        }
+        (self.lookfrom, self.lookat, self.lens_shift) = saved;
+
+        match layout {
+            ViewLayout::SideBySide => {
+                let width = self.image_width as usize;
+                let mut pixels = Vec::with_capacity(width * images.len() * self.image_height as usize);
+                for j in 0..self.image_height as usize {
+                    for image in &images {
+                        pixels.extend_from_slice(&image[j * width..(j + 1) * width]);
+                    }
+                }
+                self.write_ppm(filename, self.image_width * images.len() as i32, &pixels)?;
+            }
+            ViewLayout::Separate => {
+                for (view, image) in views.iter().zip(&images) {
+                    self.write_ppm(&view_filename(filename, &view.name), self.image_width, image)?;
+                }
             }
         }
 
         eprintln!("\nDone.");
         Ok(())
     }
+
+    /// Takes `samples_per_pixel` samples in every pixel, or as many as
+    /// adaptive sampling decides, and splats them through the reconstruction
+    /// filter.
+    fn render_film(&self, world: &dyn Hittable, lights: &dyn LightSampler) -> Film {
+        let mut film = Film::new(
+            self.image_width as usize,
+            self.image_height as usize,
+            self.filter,
+        );
+        let adaptive = match &self.adaptive {
+            Some(adaptive) => adaptive,
+            None => {
+                let samples = self.samples_per_pixel.max(0) as u32;
+                self.render_pass(&mut film, world, lights, 0, samples);
+                return film;
+            }
+        };
+
+        // Pixels only stop between passes, so each pass's pattern is whole.
+        let samples_per_pass = adaptive.min_samples.max(1);
+        let mut samples = 0;
+        while samples < adaptive.max_samples && !self.all_converged(&film) {
+            let pass_samples = samples_per_pass.min(adaptive.max_samples - samples);
+            self.render_pass(&mut film, world, lights, samples, pass_samples);
+            samples += pass_samples;
+        }
+        film
+    }
+
+    /// Adds passes to `film`, which already has `samples` samples per pixel,
+    /// until a limit of `progressive` is reached, writing the image to
+    /// `filename` and saving a checkpoint if asked after every pass.
+    fn render_progressive(
+        &self,
+        world: &dyn Hittable,
+        lights: &dyn LightSampler,
+        progressive: &Progressive,
+        filename: &str,
+        mut film: Film,
+        mut samples: u32,
+    ) -> io::Result<Film> {
+        let start = Instant::now();
+        let samples_per_pass = progressive.samples_per_pass.max(1);
+        // A resumed render may have reached its limits already.
+        if samples > 0
+            && (progressive.is_done(samples, Duration::ZERO, film.noise_level())
+                || self.all_converged(&film))
+        {
+            return Ok(film);
+        }
+
+        let mut pass = 0;
+        loop {
+            // The last pass stops at the sample limit instead of overshooting.
+            let pass_samples = progressive.max_samples.map_or(samples_per_pass, |max| {
+                samples_per_pass.min(max.saturating_sub(samples))
+            });
+            self.render_pass(&mut film, world, lights, samples, pass_samples);
+            samples += pass_samples;
+            pass += 1;
+
+            // Replace the output in one step so it's never seen half written.
+            let partial = format!("{filename}.part");
+            self.write_ppm(&partial, self.image_width, &film.pixels())?;
+            fs::rename(&partial, filename)?;
+
+            if let Some(checkpointing) = &self.checkpoint {
+                let checkpoint = Checkpoint {
+                    seed: self.seed.unwrap_or(0),
+                    samples,
+                    output: filename.to_string(),
+                    options: checkpointing.options.clone(),
+                    sampler: self.sampler,
+                    adaptive: self.adaptive,
+                    progressive: *progressive,
+                    film,
+                };
+                checkpoint.save(&checkpointing.path)?;
+                film = checkpoint.film;
+            }
+
+            let noise = film.noise_level();
+            eprintln!(
+                "\nPass {}: {} samples per pixel, noise {:.4}, {:.1?}",
+                pass,
+                samples,
+                noise,
+                start.elapsed()
+            );
+            if progressive.is_done(samples, start.elapsed(), noise) || self.all_converged(&film) {
+                return Ok(film);
+            }
+        }
+    }
+
+    /// Takes samples `first_sample` to `first_sample + samples` in every pixel
+    /// that adaptive sampling hadn't finished with when the pass began.
+    fn render_pass(
+        &self,
+        film: &mut Film,
+        world: &dyn Hittable,
+        lights: &dyn LightSampler,
+        first_sample: u32,
+        samples: u32,
+    ) {
+        let sampler = self.sampler.build(first_sample, samples as i32, self.seed);
+        bababoi::with_sampler(sampler, || {
+            for j in 0..self.image_height {
+                eprint!("\rScanlines remaining: {} ", self.image_height - j);
+                for i in 0..self.image_width {
+                    if let Some(adaptive) = &self.adaptive {
+                        if adaptive.is_done(film.variance(i as usize, j as usize)) {
+                            continue;
+                        }
+                    }
+                    for s in first_sample..first_sample + samples {
+                        bababoi::start_pixel_sample(i, j, s);
+                        let (color, offset) = self.sample_pixel(i, j, world, lights);
+                        film.add_sample(i as usize, j as usize, offset, color);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Whether adaptive sampling is on and has finished every pixel.
+    fn all_converged(&self, film: &Film) -> bool {
+        let adaptive = match &self.adaptive {
+            Some(adaptive) => adaptive,
+            None => return false,
+        };
+        (0..film.height())
+            .all(|j| (0..film.width()).all(|i| adaptive.is_done(film.variance(i, j))))
+    }
+
+    /// Writes an image of the samples each pixel took, from black for none
+    /// to white for the most taken by any pixel.
+    fn write_heatmap(&self, filename: &str, film: &Film) -> io::Result<()> {
+        let counts: Vec<u32> = (0..film.height())
+            .flat_map(|j| (0..film.width()).map(move |i| (i, j)))
+            .map(|(i, j)| film.variance(i, j).count())
+            .collect();
+        let most = counts.iter().copied().max().unwrap_or(0).max(1) as f64;
+        let pixels: Vec<Color> = counts
+            .iter()
+            .map(|&count| {
+                let level = count as f64 / most;
+                Color::new(level, level, level)
+            })
+            .collect();
+        self.write_ppm(filename, self.image_width, &pixels)
+    }
+
+    /// Writes pixel colors as a plain PPM image `width` pixels wide.
+    fn write_ppm(&self, filename: &str, width: i32, pixels: &[Color]) -> io::Result<()> {
+        let mut file = io::BufWriter::new(File::create(Path::new(filename))?);
+
+        writeln!(file, "P3")?;
+        writeln!(file, "{} {}", width, pixels.len() / width.max(1) as usize)?;
+        writeln!(file, "255")?;
+
+        for &pixel_color in pixels {
+            write_color(&mut file, pixel_color, 1)?;
+        }
+        file.flush()
+    }
+}
+
+/// Output name for one view of a multi-view render: `out.ppm` becomes
+/// `out_left.ppm` for the view named `left`.
+fn view_filename(filename: &str, view: &str) -> String {
+    let path = Path::new(filename);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("view");
+    let name = match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) => format!("{stem}_{view}.{extension}"),
+        None => format!("{stem}_{view}"),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// Medium with its coefficients upsampled to the path's wavelengths.
+fn lift_medium(wavelengths: Option<&SampledWavelengths>, medium: &Medium) -> Medium {
+    Medium::new(
+        lift(wavelengths, medium.sigma_a),
+        lift(wavelengths, medium.sigma_s),
+        medium.g,
+    )
+}
+
+/// Upsamples an RGB quantity to the path's wavelengths, if it has any.
+fn lift(wavelengths: Option<&SampledWavelengths>, c: Color) -> Color {
+    match wavelengths {
+        Some(wavelengths) => wavelengths.sample_rgb(c),
+        None => c,
+    }
+}
+
+/// Medium that a ray leaving `rec` in `direction` travels through, given the
+/// media the path is inside. Crossing a boundary enters or leaves the medium
+/// behind it; staying on the incoming side keeps the current one.
+fn medium_towards(media: &[Medium], rec: &HitRecord, direction: &Vec3) -> Option<Medium> {
+    let crosses = direction.dot(&rec.normal) < 0.0;
+    match rec.mat.interior_medium() {
+        Some(medium) if crosses && rec.front_face => Some(medium),
+        Some(medium) if crosses => {
+            let mut outside = media.to_vec();
+            if let Some(index) = outside.iter().rposition(|m| *m == medium) {
+                outside.remove(index);
+            }
+            outside.last().copied()
+        }
+        _ => media.last().copied(),
+    }
+}
+
+/// Power heuristic weight for a sample drawn with density `pdf_a` when a
+/// second strategy with density `pdf_b` could have produced it too.
+fn power_heuristic(pdf_a: f64, pdf_b: f64) -> f64 {
+    let a2 = pdf_a * pdf_a;
+    let b2 = pdf_b * pdf_b;
+    if a2 + b2 <= 0.0 {
+        0.0
+    } else {
+        a2 / (a2 + b2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable_list::HittableList;
+    use crate::sphere::Sphere;
+
+    fn looking_down_z() -> Camera {
+        let mut cam = Camera::new();
+        cam.image_width = 40;
+        cam.aspect_ratio = 2.0;
+        cam.lookfrom = Point3::new(0.0, 0.0, 5.0);
+        cam.lookat = Point3::zero();
+        cam.focus_dist = 5.0;
+        cam
+    }
+
+    /// A gray unit sphere at the origin, in front of `looking_down_z`.
+    fn one_sphere_world() -> HittableList {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Point3::zero(),
+            1.0,
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        )));
+        world
+    }
+
+    #[test]
+    fn test_shadow_rays_take_the_medium_they_travel_in() {
+        use crate::material::Dielectric;
+
+        let fog = Medium::absorbing(Color::new(0.1, 0.1, 0.1));
+        let glass = Medium::absorbing(Color::new(1.0, 0.5, 0.5));
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let hit = |from: Vec3| {
+            let ray = Ray::new(Point3::zero() - from, from);
+            let mat = Dielectric::with_interior(1.5, glass);
+            let mut rec = HitRecord::new(Point3::zero(), up, mat, 1.0);
+            rec.set_face_normal(&ray, up);
+            rec
+        };
+
+        // On the inner face, light from outside only crosses the fog.
+        let inside = hit(up);
+        let media = [fog, glass];
+        assert_eq!(medium_towards(&media, &inside, &up), Some(fog));
+        assert_eq!(medium_towards(&media, &inside, &-up), Some(glass));
+
+        // From outside, only directions into the object meet the glass.
+        let outside = hit(-up);
+        assert_eq!(medium_towards(&[fog], &outside, &-up), Some(glass));
+        assert_eq!(medium_towards(&[fog], &outside, &up), Some(fog));
+        assert_eq!(medium_towards(&[], &outside, &up), None);
+    }
+
+    #[test]
+    fn test_orthographic_rays_are_parallel() {
+        let mut cam = looking_down_z();
+        cam.projection = Projection::Orthographic { view_height: 4.0 };
+        cam.defocus_angle = 10.0;
+        cam.initialize();
+
+        let corner = cam.get_ray(0, 0, 0.0, 0.0).unwrap();
+        let center = cam.get_ray(20, 10, 0.0, 0.0).unwrap();
+        assert!((corner.direction() - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-10);
+        assert!((center.direction() - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-10);
+        // The corner pixel sits near the edge of the 8 by 4 view.
+        assert!((corner.origin().x() + 4.0).abs() < 0.2);
+        assert!((corner.origin().y() - 2.0).abs() < 0.2);
+        assert!((corner.origin().z() - 5.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_panoramic_center_looks_forward() {
+        for projection in [Projection::Equirectangular, Projection::Fisheye { fov: 180.0 }] {
+            let mut cam = looking_down_z();
+            cam.image_width = 401;
+            cam.projection = projection;
+            cam.initialize();
+
+            let ray = cam.get_ray(200, cam.image_height / 2, 0.0, 0.0).unwrap();
+            let direction = ray.direction().unit_vector();
+            assert!(direction.z() < -0.999);
+            assert!((ray.origin() - cam.lookfrom).length() < 1e-10);
+        }
+    }
+
+    #[test]
+    fn test_equirectangular_edges_look_backward() {
+        let mut cam = looking_down_z();
+        cam.projection = Projection::Equirectangular;
+        cam.initialize();
+
+        let direction = cam.get_ray(0, cam.image_height / 2, 0.0, 0.0).unwrap().direction();
+        assert!(direction.unit_vector().z() > 0.95);
+        let up = cam.get_ray(20, 0, 0.0, 0.0).unwrap().direction();
+        assert!(up.unit_vector().y() > 0.95);
+    }
+
+    #[test]
+    fn test_fisheye_corners_are_empty() {
+        let mut cam = looking_down_z();
+        cam.projection = Projection::Fisheye { fov: 180.0 };
+        cam.initialize();
+
+        assert!(cam.get_ray(0, 0, 0.0, 0.0).is_none());
+        let edge = cam.get_ray(20, 0, 0.0, 0.0).unwrap().direction().unit_vector();
+        assert!(edge.y() > 0.9);
+    }
+
+    #[test]
+    fn test_physical_camera() {
+        let physical = PhysicalCamera::default();
+        assert!((physical.vfov(1.5) - 26.99).abs() < 0.01);
+        assert!((physical.aperture_radius() - 0.008929).abs() < 1e-6);
+
+        // Sunny 16: f/16 at 1/100s and ISO 100.
+        let sunny = PhysicalCamera {
+            f_number: 16.0,
+            shutter_speed: 0.01,
+            ..physical
+        };
+        assert!((sunny.ev100() - 14.64).abs() < 0.01);
+        // Doubling the ISO doubles the exposure.
+        let faster = PhysicalCamera { iso: 200.0, ..sunny };
+        assert!((faster.exposure() / sunny.exposure() - 2.0).abs() < 1e-10);
+
+        let mut cam = looking_down_z();
+        cam.physical = Some(physical);
+        cam.initialize();
+        assert!((cam.defocus_radius - physical.aperture_radius()).abs() < 1e-12);
+        assert!((cam.exposure - physical.exposure()).abs() < 1e-12);
+
+        // The sensor is cropped to the image as rendered, after rounding.
+        cam.image_width = 25;
+        cam.aspect_ratio = 2.0;
+        cam.initialize();
+        let aspect = 25.0 / 12.0;
+        assert!((cam.effective_vfov() - physical.vfov(aspect)).abs() < 1e-12);
+        let half_height = cam.pixel_delta_v.length() * cam.image_height as f64 / 2.0;
+        let expected = (physical.vfov(aspect).to_radians() / 2.0).tan() * cam.focus_dist;
+        assert!((half_height - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_lens_rays_leave_forward() {
+        let mut cam = looking_down_z();
+        cam.lens = Some(Arc::new(LensSystem::double_gauss_50mm()));
+        cam.focus_dist = 2.0;
+        cam.initialize();
+        let lens = cam.focused_lens.clone().unwrap();
+
+        let mut passed = 0;
+        for _ in 0..100 {
+            if let Some((ray, weight)) = cam.lens_ray(&lens, 20, 10, 0.0, 0.0) {
+                assert!(ray.direction().unit_vector().z() < -0.99);
+                assert!(weight > 0.8 && weight <= 1.0);
+                passed += 1;
+            }
+        }
+        assert!(passed > 50);
+    }
+
+    #[test]
+    fn test_lens_shift_moves_every_projection() {
+        // Shifting right turns every view towards +x without moving the camera.
+        for projection in [Projection::Equirectangular, Projection::Fisheye { fov: 180.0 }] {
+            let mut cam = looking_down_z();
+            cam.image_width = 400;
+            cam.projection = projection;
+            cam.lens_shift = (0.25, 0.0);
+            cam.initialize();
+
+            let ray = cam.get_ray(200, cam.image_height / 2, -0.5, -0.5).unwrap();
+            assert!(ray.direction().unit_vector().x() > 0.7);
+        }
+
+        let mut cam = looking_down_z();
+        cam.lens = Some(Arc::new(LensSystem::double_gauss_50mm()));
+        cam.focus_dist = 2.0;
+        cam.lens_shift = (0.25, 0.0);
+        cam.initialize();
+        let lens = cam.focused_lens.clone().unwrap();
+        let ray = (0..100)
+            .find_map(|_| cam.lens_ray(&lens, 20, 10, 0.0, 0.0))
+            .unwrap()
+            .0;
+        assert!(ray.direction().unit_vector().x() > 0.1);
+    }
+
+    #[test]
+    fn test_stereo_views() {
+        let mut cam = looking_down_z();
+        cam.vfov = 90.0;
+        cam.aspect_ratio = 1.0;
+        let views = cam.stereo_views(0.064, 2.0);
+        let (left, right) = (&views[0], &views[1]);
+
+        assert!((left.lookfrom.x() + 0.032).abs() < 1e-12);
+        assert!((right.lookfrom.x() - 0.032).abs() < 1e-12);
+        assert!((left.lookat - left.lookfrom - (cam.lookat - cam.lookfrom)).length() < 1e-12);
+
+        // A point on the rig's axis at the convergence distance lands in the
+        // middle of both images.
+        let point = Point3::new(0.0, 0.0, 5.0 - 2.0);
+        for view in &views {
+            cam.lookfrom = view.lookfrom;
+            cam.lookat = view.lookat;
+            cam.lens_shift = view.lens_shift;
+            cam.initialize();
+            let to_point = (point - cam.center).unit_vector();
+            let middle = cam.pixel00_loc
+                + cam.pixel_delta_u * (cam.image_width as f64 / 2.0 - 0.5)
+                + cam.pixel_delta_v * (cam.image_height as f64 / 2.0 - 0.5);
+            let to_middle = (middle - cam.center).unit_vector();
+            assert!((to_point - to_middle).length() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_view_filename() {
+        assert_eq!(view_filename("out.ppm", "left"), "out_left.ppm");
+        assert_eq!(view_filename("renders/out", "cam3"), "renders/out_cam3");
+    }
+
+    #[test]
+    fn test_seeded_renders_repeat() {
+        use crate::sampler::SamplerKind;
+
+        let world = one_sphere_world();
+
+        let mut cam = looking_down_z();
+        cam.image_width = 8;
+        cam.samples_per_pixel = 4;
+        cam.defocus_angle = 2.0;
+        let bits = |c: &Color| [c.x().to_bits(), c.y().to_bits(), c.z().to_bits()];
+
+        for kind in [SamplerKind::Independent, SamplerKind::Sobol] {
+            cam.sampler = kind;
+            cam.seed = Some(7);
+            cam.initialize();
+            let lights = cam.build_lights(&world);
+            let first = cam.render_film(&world, lights.as_ref()).pixels();
+            let second = cam.render_film(&world, lights.as_ref()).pixels();
+            assert!(first.iter().zip(&second).all(|(a, b)| bits(a) == bits(b)));
+
+            // A pixel rendered on its own, as another thread would, matches.
+            let alone = bababoi::with_sampler(cam.sampler.build(0, 4, cam.seed), || {
+                (0..4).fold(Color::zero(), |sum, s| {
+                    bababoi::start_pixel_sample(3, 2, s);
+                    sum + cam.sample_pixel(3, 2, &world, lights.as_ref()).0
+                })
+            }) / 4.0;
+            assert_eq!(bits(&alone), bits(&first[2 * 8 + 3]));
+
+            cam.seed = Some(8);
+            let other = cam.render_film(&world, lights.as_ref()).pixels();
+            assert!(first.iter().zip(&other).any(|(a, b)| bits(a) != bits(b)));
+        }
+    }
+
+    #[test]
+    fn test_adaptive_sampling_skips_smooth_pixels() {
+        let world = one_sphere_world();
+
+        let mut cam = looking_down_z();
+        cam.image_width = 8;
+        cam.vfov = 30.0;
+        cam.seed = Some(3);
+        cam.adaptive = Some(AdaptiveSampling {
+            min_samples: 4,
+            max_samples: 256,
+            threshold: 0.02,
+            write_heatmap: false,
+        });
+        cam.initialize();
+        let lights = cam.build_lights(&world);
+        let film = cam.render_film(&world, lights.as_ref());
+
+        let sky = film.variance(0, 0).count();
+        let edge = film.variance(2, 1).count();
+        assert!(sky < 16, "sky took {sky} samples");
+        assert!(edge > 4 * sky, "sphere's edge took {edge} samples");
+    }
+
+    #[test]
+    fn test_progressive_rewrites_output() {
+        use crate::ppm::read_ppm;
+
+        let mut cam = looking_down_z();
+        cam.image_width = 8;
+        cam.progressive = Some(Progressive {
+            samples_per_pass: 2,
+            max_samples: Some(6),
+            ..Progressive::default()
+        });
+        let path = std::env::temp_dir().join(format!("progressive-{}.ppm", std::process::id()));
+        let filename = path.to_str().unwrap();
+        cam.render_to_file(&HittableList::new(), filename).unwrap();
+
+        let image = read_ppm(&path).unwrap();
+        assert_eq!((image.width, image.height), (8, 4));
+        assert!(!Path::new(&format!("{filename}.part")).exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_progressive_stops_at_max_samples() {
+        let world = one_sphere_world();
+        let mut cam = looking_down_z();
+        cam.image_width = 8;
+        cam.seed = Some(5);
+        cam.initialize();
+        let lights = cam.build_lights(&world);
+        let progressive = Progressive {
+            samples_per_pass: 4,
+            max_samples: Some(6),
+            ..Progressive::default()
+        };
+        let path = std::env::temp_dir().join(format!("max-samples-{}.ppm", std::process::id()));
+        let filename = path.to_str().unwrap();
+        let new_film = || Film::new(8, 4, cam.filter);
+
+        let film = cam
+            .render_progressive(&world, lights.as_ref(), &progressive, filename, new_film(), 0)
+            .unwrap();
+        assert_eq!(film.variance(3, 2).count(), 6);
+
+        // Nothing is added to a film that already reached the limit.
+        let film = cam
+            .render_progressive(&world, lights.as_ref(), &progressive, filename, film, 6)
+            .unwrap();
+        assert_eq!(film.variance(3, 2).count(), 6);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_resumed_render_matches_uninterrupted() {
+        let world = one_sphere_world();
+        let dir = std::env::temp_dir();
+        let id = std::process::id();
+        let whole = dir.join(format!("whole-{id}.ppm"));
+        let resumed = dir.join(format!("resumed-{id}.ppm"));
+        let checkpoint_path = dir.join(format!("resumed-{id}.ckpt"));
+
+        let camera = |progressive| {
+            let mut cam = looking_down_z();
+            cam.image_width = 8;
+            cam.vfov = 30.0;
+            cam.seed = Some(11);
+            cam.progressive = Some(progressive);
+            cam
+        };
+        let passes = |max_samples| Progressive {
+            samples_per_pass: 2,
+            max_samples: Some(max_samples),
+            ..Progressive::default()
+        };
+
+        camera(passes(8))
+            .render_to_file(&world, whole.to_str().unwrap())
+            .unwrap();
+
+        let mut first = camera(passes(4));
+        first.checkpoint = Some(Checkpointing {
+            path: checkpoint_path.to_str().unwrap().to_string(),
+            options: Vec::new(),
+        });
+        first
+            .render_to_file(&world, resumed.to_str().unwrap())
+            .unwrap();
+        let checkpoint = Checkpoint::load(&checkpoint_path).unwrap();
+        assert_eq!(checkpoint.samples, 4);
+        let mut other_sampler = camera(passes(8));
+        other_sampler.sampler = crate::sampler::SamplerKind::Sobol;
+        assert!(other_sampler.resume_to_file(&world, checkpoint).is_err());
+
+        let checkpoint = Checkpoint::load(&checkpoint_path).unwrap();
+        camera(passes(8)).resume_to_file(&world, checkpoint).unwrap();
+
+        assert_eq!(fs::read(&whole).unwrap(), fs::read(&resumed).unwrap());
+        for path in [whole, resumed, checkpoint_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
 }
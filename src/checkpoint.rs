@@ -0,0 +1,309 @@
+use crate::adaptive::{AdaptiveSampling, PixelVariance};
+use crate::film::Film;
+use crate::filter::Filter;
+use crate::progressive::Progressive;
+use crate::sampler::SamplerKind;
+use crate::vec3::Color;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+const MAGIC: &[u8; 8] = b"RTCHECK2";
+
+/// Saved state of a progressive render, enough to pick it up again and add
+/// more samples as if it had never stopped.
+pub struct Checkpoint {
+    /// Seed every sample's random numbers are derived from.
+    pub seed: u64,
+    /// Samples per pixel taken so far. The next pass continues the sample
+    /// sequences from here.
+    pub samples: u32,
+    /// Image the render writes to.
+    pub output: String,
+    /// Command-line options the render was started with, so it can be
+    /// resumed with the same scene and settings.
+    pub options: Vec<String>,
+    /// Sampling settings the samples so far were taken with. A resumed
+    /// render must use the same ones to continue their patterns.
+    pub sampler: SamplerKind,
+    pub adaptive: Option<AdaptiveSampling>,
+    /// Pass settings the render was started with. A resumed render keeps the
+    /// pass size but may change the limits.
+    pub progressive: Progressive,
+    pub film: Film,
+}
+
+/// Where a progressive render saves its checkpoint after every pass.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpointing {
+    pub path: String,
+    /// Stored in the checkpoint as `Checkpoint::options`.
+    pub options: Vec<String>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl Checkpoint {
+    /// Writes the checkpoint, replacing any earlier one in a single step so
+    /// a crash while saving leaves the last good checkpoint in place.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".part");
+
+        let mut out = BufWriter::new(File::create(&partial)?);
+        self.write(&mut out)?;
+        out.flush()?;
+        drop(out);
+        fs::rename(&partial, path)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    fn write(&self, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&self.seed.to_le_bytes())?;
+        out.write_all(&self.samples.to_le_bytes())?;
+        write_string(out, &self.output)?;
+        out.write_all(&(self.options.len() as u32).to_le_bytes())?;
+        for option in &self.options {
+            write_string(out, option)?;
+        }
+
+        let sampler = match self.sampler {
+            SamplerKind::Independent => 0u8,
+            SamplerKind::Stratified => 1,
+            SamplerKind::Halton => 2,
+            SamplerKind::Sobol => 3,
+        };
+        out.write_all(&[sampler, self.adaptive.is_some() as u8])?;
+        if let Some(adaptive) = &self.adaptive {
+            out.write_all(&adaptive.min_samples.to_le_bytes())?;
+            out.write_all(&adaptive.max_samples.to_le_bytes())?;
+            write_f64s(out, &[adaptive.threshold])?;
+            out.write_all(&[adaptive.write_heatmap as u8])?;
+        }
+
+        let progressive = &self.progressive;
+        out.write_all(&progressive.samples_per_pass.to_le_bytes())?;
+        // Unset limits are stored as NaN, or as zero samples.
+        let time_limit = progressive.time_limit.map_or(f64::NAN, |t| t.as_secs_f64());
+        let target_noise = progressive.target_noise.unwrap_or(f64::NAN);
+        write_f64s(out, &[time_limit, target_noise])?;
+        out.write_all(&progressive.max_samples.unwrap_or(0).to_le_bytes())?;
+
+        let film = &self.film;
+        out.write_all(&(film.width() as u32).to_le_bytes())?;
+        out.write_all(&(film.height() as u32).to_le_bytes())?;
+        let (tag, params) = match film.filter() {
+            Filter::Box { radius } => (0u8, [radius, 0.0, 0.0]),
+            Filter::Tent { radius } => (1, [radius, 0.0, 0.0]),
+            Filter::Gaussian { radius, sigma } => (2, [radius, sigma, 0.0]),
+            Filter::Mitchell { radius, b, c } => (3, [radius, b, c]),
+            Filter::Lanczos { radius } => (4, [radius, 0.0, 0.0]),
+        };
+        out.write_all(&[tag])?;
+        write_f64s(out, &params)?;
+
+        let (sums, weights, variances) = film.parts();
+        for ((sum, &weight), variance) in sums.iter().zip(weights).zip(variances) {
+            write_f64s(out, &[sum.x(), sum.y(), sum.z(), weight])?;
+            out.write_all(&variance.count().to_le_bytes())?;
+            write_f64s(out, &[variance.mean(), variance.m2()])?;
+        }
+        Ok(())
+    }
+
+    fn read(input: &mut dyn Read) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a render checkpoint"));
+        }
+        let seed = read_u64(input)?;
+        let samples = read_u32(input)?;
+        let output = read_string(input)?;
+        let option_count = read_u32(input)?;
+        let options = (0..option_count)
+            .map(|_| read_string(input))
+            .collect::<io::Result<_>>()?;
+
+        let sampler = match read_u8(input)? {
+            0 => SamplerKind::Independent,
+            1 => SamplerKind::Stratified,
+            2 => SamplerKind::Halton,
+            3 => SamplerKind::Sobol,
+            _ => return Err(invalid("unknown sampler in checkpoint")),
+        };
+        let adaptive = match read_u8(input)? {
+            0 => None,
+            _ => Some(AdaptiveSampling {
+                min_samples: read_u32(input)?,
+                max_samples: read_u32(input)?,
+                threshold: read_f64(input)?,
+                write_heatmap: read_u8(input)? != 0,
+            }),
+        };
+
+        let samples_per_pass = read_u32(input)?;
+        let time_limit = match read_f64(input)? {
+            t if t.is_nan() => None,
+            t => Some(
+                Duration::try_from_secs_f64(t)
+                    .map_err(|_| invalid("bad time limit in checkpoint"))?,
+            ),
+        };
+        let target_noise = Some(read_f64(input)?).filter(|n| !n.is_nan());
+        let max_samples = Some(read_u32(input)?).filter(|&max| max > 0);
+        let progressive = Progressive {
+            samples_per_pass,
+            time_limit,
+            target_noise,
+            max_samples,
+        };
+
+        let width = read_u32(input)? as usize;
+        let height = read_u32(input)? as usize;
+        let mut tag = [0];
+        input.read_exact(&mut tag)?;
+        let [radius, a, b] = [read_f64(input)?, read_f64(input)?, read_f64(input)?];
+        let filter = match tag[0] {
+            0 => Filter::Box { radius },
+            1 => Filter::Tent { radius },
+            2 => Filter::Gaussian { radius, sigma: a },
+            3 => Filter::Mitchell { radius, b: a, c: b },
+            4 => Filter::Lanczos { radius },
+            _ => return Err(invalid("unknown filter in checkpoint")),
+        };
+
+        let pixels = width
+            .checked_mul(height)
+            .ok_or_else(|| invalid("bad checkpoint size"))?;
+        let mut sums = Vec::new();
+        let mut weights = Vec::new();
+        let mut variances = Vec::new();
+        for _ in 0..pixels {
+            sums.push(Color::new(
+                read_f64(input)?,
+                read_f64(input)?,
+                read_f64(input)?,
+            ));
+            weights.push(read_f64(input)?);
+            let count = read_u32(input)?;
+            variances.push(PixelVariance::from_parts(
+                count,
+                read_f64(input)?,
+                read_f64(input)?,
+            ));
+        }
+        let film = Film::from_parts(width, height, filter, sums, weights, variances)
+            .ok_or_else(|| invalid("bad checkpoint size"))?;
+
+        Ok(Self {
+            seed,
+            samples,
+            output,
+            options,
+            sampler,
+            adaptive,
+            progressive,
+            film,
+        })
+    }
+}
+
+fn write_f64s(out: &mut dyn Write, values: &[f64]) -> io::Result<()> {
+    for v in values {
+        out.write_all(&v.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn write_string(out: &mut dyn Write, s: &str) -> io::Result<()> {
+    out.write_all(&(s.len() as u32).to_le_bytes())?;
+    out.write_all(s.as_bytes())
+}
+
+fn read_u8(input: &mut dyn Read) -> io::Result<u8> {
+    let mut byte = [0];
+    input.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_u32(input: &mut dyn Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(input: &mut dyn Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64(input: &mut dyn Read) -> io::Result<f64> {
+    Ok(f64::from_bits(read_u64(input)?))
+}
+
+fn read_string(input: &mut dyn Read) -> io::Result<String> {
+    let len = read_u32(input)? as usize;
+    let mut bytes = Vec::new();
+    input.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(bytes).map_err(|_| invalid("bad text in checkpoint"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut film = Film::new(3, 2, Filter::mitchell(2.0));
+        film.add_sample(1, 0, (0.2, -0.1), Color::new(0.5, 1.0, 2.0));
+        film.add_sample(1, 0, (-0.4, 0.3), Color::new(0.1, 0.0, 0.3));
+        film.add_sample(2, 1, (0.0, 0.0), Color::new(4.0, 4.0, 4.0));
+        let checkpoint = Checkpoint {
+            seed: 42,
+            samples: 2,
+            output: "out.ppm".to_string(),
+            options: vec!["--sky".to_string(), "--seed".to_string(), "42".to_string()],
+            sampler: SamplerKind::Sobol,
+            adaptive: Some(AdaptiveSampling::default()),
+            progressive: Progressive {
+                time_limit: Some(Duration::from_millis(1500)),
+                max_samples: Some(64),
+                ..Progressive::default()
+            },
+            film,
+        };
+
+        let mut data = Vec::new();
+        checkpoint.write(&mut data).unwrap();
+        let loaded = Checkpoint::read(&mut data.as_slice()).unwrap();
+
+        assert_eq!((loaded.seed, loaded.samples), (42, 2));
+        assert_eq!(loaded.output, "out.ppm");
+        assert_eq!(loaded.options, checkpoint.options);
+        assert_eq!(loaded.sampler, checkpoint.sampler);
+        assert_eq!(loaded.adaptive, checkpoint.adaptive);
+        assert_eq!(loaded.progressive, checkpoint.progressive);
+        assert_eq!(loaded.film.filter(), Filter::mitchell(2.0));
+        for (i, j) in [(0, 0), (1, 0), (2, 1)] {
+            let (a, b) = (loaded.film.pixel(i, j), checkpoint.film.pixel(i, j));
+            assert_eq!((a.x(), a.y(), a.z()), (b.x(), b.y(), b.z()));
+            assert_eq!(loaded.film.variance(i, j), checkpoint.film.variance(i, j));
+        }
+
+        assert!(Checkpoint::read(&mut &data[..data.len() - 1]).is_err());
+        assert!(Checkpoint::read(&mut &b"P3 1 1 255"[..]).is_err());
+    }
+}
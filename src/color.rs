@@ -46,6 +46,21 @@ pub fn color_to_rgba(pixel_color: Color, samples_per_pixel: i32) -> [u8; 4] {
     [r, g, b, 255]
 }
 
+/// Converts CIE XYZ to linear sRGB. Out-of-gamut colors produce negative
+/// components, which are left for the caller to handle.
+pub fn xyz_to_rgb(x: f64, y: f64, z: f64) -> Color {
+    Color::new(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    )
+}
+
+/// Relative luminance of a linear sRGB color.
+pub fn luminance(c: Color) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
 fn linear_to_gamma(linear_component: f64) -> f64 {
     linear_component.sqrt()
 }
@@ -0,0 +1,153 @@
+use crate::adaptive::PixelVariance;
+use crate::color::luminance;
+use crate::filter::Filter;
+use crate::vec3::Color;
+
+/// Image being rendered, holding the filter-weighted sum of the samples
+/// splatted onto each pixel and the sum of their weights, along with the
+/// statistics of the samples taken in each pixel.
+pub struct Film {
+    width: usize,
+    height: usize,
+    filter: Filter,
+    sums: Vec<Color>,
+    weights: Vec<f64>,
+    variances: Vec<PixelVariance>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize, filter: Filter) -> Self {
+        Self {
+            width,
+            height,
+            filter,
+            sums: vec![Color::zero(); width * height],
+            weights: vec![0.0; width * height],
+            variances: vec![PixelVariance::default(); width * height],
+        }
+    }
+
+    /// Rebuilds a film from saved state, as read back from a checkpoint.
+    /// Returns `None` if the buffers don't match the size.
+    pub(crate) fn from_parts(
+        width: usize,
+        height: usize,
+        filter: Filter,
+        sums: Vec<Color>,
+        weights: Vec<f64>,
+        variances: Vec<PixelVariance>,
+    ) -> Option<Self> {
+        let pixels = width * height;
+        if sums.len() != pixels || weights.len() != pixels || variances.len() != pixels {
+            return None;
+        }
+        Some(Self {
+            width,
+            height,
+            filter,
+            sums,
+            weights,
+            variances,
+        })
+    }
+
+    /// Weighted sums, weights and statistics of every pixel.
+    pub(crate) fn parts(&self) -> (&[Color], &[f64], &[PixelVariance]) {
+        (&self.sums, &self.weights, &self.variances)
+    }
+
+    pub fn filter(&self) -> Filter {
+        self.filter
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Adds a sample taken in pixel `(i, j)` at `offset` from its center to
+    /// every pixel whose center lies within the filter's radius.
+    pub fn add_sample(&mut self, i: usize, j: usize, offset: (f64, f64), color: Color) {
+        self.variances[j * self.width + i].add(luminance(color));
+
+        let radius = self.filter.radius();
+        let (cx, cy) = (i as f64 + offset.0, j as f64 + offset.1);
+        let x0 = ((cx - radius).floor() as i64 + 1).max(0);
+        let x1 = ((cx + radius).ceil() as i64 - 1).min(self.width as i64 - 1);
+        let y0 = ((cy - radius).floor() as i64 + 1).max(0);
+        let y1 = ((cy + radius).ceil() as i64 - 1).min(self.height as i64 - 1);
+
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let weight = self.filter.evaluate(x as f64 - cx, y as f64 - cy);
+                if weight != 0.0 {
+                    let index = y as usize * self.width + x as usize;
+                    self.sums[index] += color * weight;
+                    self.weights[index] += weight;
+                }
+            }
+        }
+    }
+
+    /// Statistics of the samples taken in pixel `(i, j)`.
+    pub fn variance(&self, i: usize, j: usize) -> &PixelVariance {
+        &self.variances[j * self.width + i]
+    }
+
+    /// Average relative error of the pixels' means, a measure of how noisy
+    /// the image still is.
+    pub fn noise_level(&self) -> f64 {
+        let total: f64 = self.variances.iter().map(|v| v.relative_error()).sum();
+        total / self.variances.len().max(1) as f64
+    }
+
+    /// Reconstructed color of pixel `(i, j)`.
+    pub fn pixel(&self, i: usize, j: usize) -> Color {
+        let index = j * self.width + i;
+        if self.weights[index] <= 0.0 {
+            return Color::zero();
+        }
+        self.sums[index] / self.weights[index]
+    }
+
+    /// Reconstructed colors row by row from the top left.
+    pub fn pixels(&self) -> Vec<Color> {
+        (0..self.height)
+            .flat_map(|j| (0..self.width).map(move |i| (i, j)))
+            .map(|(i, j)| self.pixel(i, j))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_box_filter_averages_within_pixel() {
+        let mut film = Film::new(2, 1, Filter::default());
+        film.add_sample(0, 0, (-0.3, 0.0), Color::new(1.0, 0.0, 0.0));
+        film.add_sample(0, 0, (0.4, -0.4), Color::new(0.0, 1.0, 0.0));
+        film.add_sample(1, 0, (0.0, 0.0), Color::new(0.0, 0.0, 1.0));
+
+        assert!((film.pixel(0, 0) - Color::new(0.5, 0.5, 0.0)).length() < 1e-12);
+        assert!((film.pixel(1, 0) - Color::new(0.0, 0.0, 1.0)).length() < 1e-12);
+        assert_eq!(film.variance(0, 0).count(), 2);
+    }
+
+    #[test]
+    fn test_wide_filter_splats_to_neighbors() {
+        let mut film = Film::new(3, 3, Filter::Tent { radius: 1.5 });
+        film.add_sample(1, 1, (0.0, 0.0), Color::new(1.0, 1.0, 1.0));
+        for (i, j) in [(0, 0), (1, 1), (2, 1)] {
+            assert!((film.pixel(i, j).y() - 1.0).abs() < 1e-12);
+        }
+
+        film.add_sample(2, 1, (0.4, 0.0), Color::zero());
+        // The darker sample is nearer the right column.
+        assert!(film.pixel(2, 1).y() < film.pixel(0, 1).y());
+    }
+}
@@ -0,0 +1,125 @@
+use std::f64::consts::PI;
+
+/// Reconstruction filter weighting each sample's contribution to the pixels
+/// around it. Offsets and radii are in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    /// Equal weight everywhere within the radius. A radius of 0.5 averages
+    /// the samples inside each pixel.
+    Box { radius: f64 },
+    /// Weight falling linearly to zero at the radius.
+    Tent { radius: f64 },
+    /// Gaussian with standard deviation `sigma`, shifted down to reach zero
+    /// at the radius.
+    Gaussian { radius: f64, sigma: f64 },
+    /// Mitchell-Netravali cubic. `b = c = 1/3` balances blurring against
+    /// ringing.
+    Mitchell { radius: f64, b: f64, c: f64 },
+    /// Sinc windowed by a wider sinc, reaching zero at the radius. Sharpest
+    /// of the filters, with some ringing around edges.
+    Lanczos { radius: f64 },
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::Box { radius: 0.5 }
+    }
+}
+
+impl Filter {
+    /// Mitchell-Netravali filter with the recommended `b = c = 1/3`.
+    pub fn mitchell(radius: f64) -> Self {
+        Filter::Mitchell {
+            radius,
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        }
+    }
+
+    pub fn radius(&self) -> f64 {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius } => radius,
+        }
+    }
+
+    /// Weight of a sample `(x, y)` pixels away from a pixel center. Mitchell
+    /// and Lanczos filters go negative in places.
+    pub fn evaluate(&self, x: f64, y: f64) -> f64 {
+        let radius = self.radius();
+        if x.abs() > radius || y.abs() > radius {
+            return 0.0;
+        }
+        self.evaluate_1d(x, radius) * self.evaluate_1d(y, radius)
+    }
+
+    fn evaluate_1d(&self, x: f64, radius: f64) -> f64 {
+        match *self {
+            Filter::Box { .. } => 1.0,
+            Filter::Tent { .. } => radius - x.abs(),
+            Filter::Gaussian { sigma, .. } => {
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(radius)).max(0.0)
+            }
+            Filter::Mitchell { b, c, .. } => {
+                // The cubic spans [-2, 2], stretched to cover the radius.
+                let x = (2.0 * x / radius).abs();
+                if x > 1.0 {
+                    ((-b - 6.0 * c) * x * x * x
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                } else {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                }
+            }
+            Filter::Lanczos { .. } => sinc(x) * sinc(x / radius),
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filters_vanish_at_radius() {
+        let filters = [
+            Filter::Tent { radius: 1.0 },
+            Filter::Gaussian {
+                radius: 1.5,
+                sigma: 0.5,
+            },
+            Filter::mitchell(2.0),
+            Filter::Lanczos { radius: 3.0 },
+        ];
+        for filter in filters {
+            let r = filter.radius();
+            assert!(filter.evaluate(0.0, 0.0) > 0.0);
+            assert!(filter.evaluate(r, 0.0).abs() < 1e-9, "{filter:?}");
+            assert_eq!(filter.evaluate(r + 0.1, 0.0), 0.0);
+        }
+    }
+
+    #[test]
+    fn test_negative_lobes() {
+        assert!(Filter::mitchell(2.0).evaluate(1.5, 0.0) < 0.0);
+        assert!(Filter::Lanczos { radius: 3.0 }.evaluate(1.5, 0.0) < 0.0);
+        assert!(Filter::Tent { radius: 2.0 }.evaluate(1.5, 0.5) > 0.0);
+    }
+}
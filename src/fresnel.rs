@@ -0,0 +1,266 @@
+use crate::vec3::Color;
+use std::f64::consts::PI;
+
+/// Minimal complex number for conductor Fresnel terms.
+#[derive(Debug, Clone, Copy)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    fn add(self, o: Complex) -> Complex {
+        Complex::new(self.re + o.re, self.im + o.im)
+    }
+
+    fn sub(self, o: Complex) -> Complex {
+        Complex::new(self.re - o.re, self.im - o.im)
+    }
+
+    fn mul(self, o: Complex) -> Complex {
+        Complex::new(
+            self.re * o.re - self.im * o.im,
+            self.re * o.im + self.im * o.re,
+        )
+    }
+
+    fn div(self, o: Complex) -> Complex {
+        let scale = 1.0 / (o.re * o.re + o.im * o.im);
+        Complex::new(
+            (self.re * o.re + self.im * o.im) * scale,
+            (self.im * o.re - self.re * o.im) * scale,
+        )
+    }
+
+    fn scale(self, s: f64) -> Complex {
+        Complex::new(self.re * s, self.im * s)
+    }
+
+    fn norm(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    fn exp(self) -> Complex {
+        let m = self.re.exp();
+        Complex::new(m * self.im.cos(), m * self.im.sin())
+    }
+
+    fn sqrt(self) -> Complex {
+        let n = self.norm().sqrt();
+        if n == 0.0 {
+            return Complex::new(0.0, 0.0);
+        }
+        let t1 = (0.5 * (n + self.re.abs())).sqrt();
+        let t2 = 0.5 * self.im / t1;
+        if self.re >= 0.0 {
+            Complex::new(t1, t2)
+        } else {
+            Complex::new(t2.abs(), t1.copysign(self.im))
+        }
+    }
+}
+
+/// How dielectric interfaces compute their Fresnel reflectance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FresnelModel {
+    /// Schlick's polynomial approximation.
+    Schlick,
+    /// The exact unpolarized Fresnel equations.
+    Exact,
+}
+
+impl FresnelModel {
+    /// Reflectance for light arriving at `cos_theta_i` on an interface with
+    /// relative index of refraction `eta` (transmitted over incident side).
+    pub fn dielectric(self, cos_theta_i: f64, eta: f64) -> f64 {
+        match self {
+            FresnelModel::Schlick => dielectric_schlick(cos_theta_i, eta),
+            FresnelModel::Exact => dielectric(cos_theta_i, eta),
+        }
+    }
+}
+
+/// Schlick's approximation, taking the cosine on the incident side and the
+/// ratio of refraction indices.
+pub fn schlick(cosine: f64, ref_idx: f64) -> f64 {
+    let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
+    r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+}
+
+/// Schlick's approximation with the same conventions as `dielectric`. When
+/// leaving the denser medium the transmitted cosine is used, and total
+/// internal reflection returns 1.
+pub fn dielectric_schlick(cos_theta_i: f64, eta: f64) -> f64 {
+    let (cos_i, eta) = if cos_theta_i < 0.0 {
+        (-cos_theta_i, 1.0 / eta)
+    } else {
+        (cos_theta_i, eta)
+    };
+    let cos_i = cos_i.min(1.0);
+
+    if eta >= 1.0 {
+        return schlick(cos_i, eta);
+    }
+
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    schlick((1.0 - sin2_t).sqrt(), eta)
+}
+
+/// Unpolarized Fresnel reflectance of a dielectric interface. `eta` is the
+/// index on the far side of the normal over the index on the near side;
+/// negative cosines mean the light arrives from the far side.
+pub fn dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let (cos_i, eta) = if cos_theta_i < 0.0 {
+        (-cos_theta_i, 1.0 / eta)
+    } else {
+        (cos_theta_i, eta)
+    };
+    let cos_i = cos_i.min(1.0);
+
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).max(0.0).sqrt();
+
+    let r_parl = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perp = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parl * r_parl + r_perp * r_perp) / 2.0
+}
+
+/// Unpolarized Fresnel reflectance of a conductor with complex index of
+/// refraction `eta + i k`, for a single wavelength.
+pub fn conductor_scalar(cos_theta_i: f64, eta: f64, k: f64) -> f64 {
+    let cos_i = cos_theta_i.clamp(0.0, 1.0);
+    let eta = Complex::new(eta, k);
+    let sin2_i = 1.0 - cos_i * cos_i;
+    let sin2_t = Complex::new(sin2_i, 0.0).div(eta.mul(eta));
+    let cos_t = Complex::new(1.0, 0.0).sub(sin2_t).sqrt();
+
+    let ci = Complex::new(cos_i, 0.0);
+    let r_parl = eta.scale(cos_i).sub(cos_t).div(eta.scale(cos_i).add(cos_t));
+    let r_perp = ci.sub(eta.mul(cos_t)).div(ci.add(eta.mul(cos_t)));
+
+    (r_parl.norm() + r_perp.norm()) / 2.0
+}
+
+/// Conductor Fresnel reflectance evaluated per RGB channel.
+pub fn conductor(cos_theta_i: f64, eta: Color, k: Color) -> Color {
+    Color::new(
+        conductor_scalar(cos_theta_i, eta.x(), k.x()),
+        conductor_scalar(cos_theta_i, eta.y(), k.y()),
+        conductor_scalar(cos_theta_i, eta.z(), k.z()),
+    )
+}
+
+/// Unpolarized reflectance of a thin film of index `film_eta` and thickness
+/// `thickness_nm` lying on a substrate of complex index `eta_t + i k_t`, lit
+/// from a medium of index `eta_i`. Reflections inside the film interfere,
+/// so the result depends on the wavelength (Airy summation).
+pub fn thin_film(
+    cos_theta_i: f64,
+    eta_i: f64,
+    film_eta: f64,
+    thickness_nm: f64,
+    eta_t: f64,
+    k_t: f64,
+    wavelength_nm: f64,
+) -> f64 {
+    let cos_i = cos_theta_i.clamp(0.0, 1.0);
+    let sin2_i = 1.0 - cos_i * cos_i;
+    let one = Complex::new(1.0, 0.0);
+
+    let n1 = Complex::new(eta_i, 0.0);
+    let n2 = Complex::new(film_eta, 0.0);
+    let n3 = Complex::new(eta_t, k_t);
+    let cos1 = Complex::new(cos_i, 0.0);
+    // Snell's law with complex cosines covers evanescent waves and metals.
+    let cos2 = one.sub(Complex::new(sin2_i * (eta_i / film_eta).powi(2), 0.0)).sqrt();
+    let cos3 = one
+        .sub(Complex::new(sin2_i * eta_i * eta_i, 0.0).div(n3.mul(n3)))
+        .sqrt();
+
+    // Both interfaces use the same sign convention, so a film of zero
+    // thickness collapses to the plain substrate interface.
+    let r_perp = |na: Complex, ca: Complex, nb: Complex, cb: Complex| {
+        na.mul(ca).sub(nb.mul(cb)).div(na.mul(ca).add(nb.mul(cb)))
+    };
+    let r_parl = |na: Complex, ca: Complex, nb: Complex, cb: Complex| {
+        nb.mul(ca).sub(na.mul(cb)).div(nb.mul(ca).add(na.mul(cb)))
+    };
+
+    // Round-trip phase through the film, exp(2i delta).
+    let delta = n2.mul(cos2).scale(2.0 * PI * thickness_nm / wavelength_nm);
+    let phase = Complex::new(-2.0 * delta.im, 2.0 * delta.re).exp();
+
+    let airy = |r12: Complex, r23: Complex| {
+        let r23 = r23.mul(phase);
+        r12.add(r23).div(one.add(r12.mul(r23))).norm()
+    };
+
+    let perp = airy(r_perp(n1, cos1, n2, cos2), r_perp(n2, cos2, n3, cos3));
+    let parl = airy(r_parl(n1, cos1, n2, cos2), r_parl(n2, cos2, n3, cos3));
+    ((perp + parl) / 2.0).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dielectric_normal_incidence() {
+        let r0 = ((1.0 - 1.5) / (1.0 + 1.5_f64)).powi(2);
+        assert!((dielectric(1.0, 1.5) - r0).abs() < 1e-10);
+        assert!((dielectric_schlick(1.0, 1.5) - r0).abs() < 1e-10);
+        assert!((dielectric(-1.0, 1.5) - r0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_dielectric_total_internal_reflection() {
+        assert_eq!(dielectric(0.2, 1.0 / 1.5), 1.0);
+        assert_eq!(dielectric_schlick(0.2, 1.0 / 1.5), 1.0);
+        assert!(dielectric(0.9, 1.0 / 1.5) < 1.0);
+    }
+
+    #[test]
+    fn test_conductor_normal_incidence() {
+        // At normal incidence F = ((n - 1)^2 + k^2) / ((n + 1)^2 + k^2).
+        let (n, k) = (0.2, 3.9);
+        let expected = ((n - 1.0) * (n - 1.0) + k * k) / ((n + 1.0) * (n + 1.0) + k * k);
+        assert!((conductor_scalar(1.0, n, k) - expected).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_conductor_grazing_is_white() {
+        assert!((conductor_scalar(0.0, 0.2, 3.9) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_thin_film_zero_thickness_is_bare_interface() {
+        for cos in [1.0, 0.7, 0.2] {
+            let film = thin_film(cos, 1.0, 1.33, 0.0, 1.5, 0.0, 550.0);
+            assert!((film - dielectric(cos, 1.5)).abs() < 1e-9);
+
+            let film = thin_film(cos, 1.0, 1.6, 0.0, 0.2, 3.9, 550.0);
+            assert!((film - conductor_scalar(cos, 0.2, 3.9)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_thin_film_quarter_wave_coating() {
+        // A quarter-wave layer of index sqrt(n) cancels reflection from glass
+        // at its design wavelength, but not away from it.
+        let n = 1.5_f64;
+        let film = n.sqrt();
+        let thickness = 550.0 / (4.0 * film);
+        assert!(thin_film(1.0, 1.0, film, thickness, n, 0.0, 550.0) < 1e-9);
+        assert!(thin_film(1.0, 1.0, film, thickness, n, 0.0, 400.0) > 1e-3);
+    }
+}
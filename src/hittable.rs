@@ -1,3 +1,4 @@
+use crate::light::Light;
 use crate::material::Material;
 use crate::ray::Ray;
 use crate::vec3::{Point3, Vec3};
@@ -9,6 +10,8 @@ pub struct HitRecord {
     pub normal: Vec3,
     pub mat: Arc<dyn Material>,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
 }
 
@@ -19,6 +22,8 @@ impl HitRecord {
             normal,
             mat,
             t,
+            u: 0.0,
+            v: 0.0,
             front_face: false,
         }
     }
@@ -35,4 +40,7 @@ impl HitRecord {
 
 pub trait Hittable {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool;
+
+    /// Appends any emissive geometry that can be sampled directly as a light.
+    fn collect_lights(&self, _lights: &mut Vec<Light>) {}
 }
@@ -1,10 +1,17 @@
 use crate::hittable::{HitRecord, Hittable};
+use crate::light::Light;
 use crate::ray::Ray;
 
 pub struct HittableList {
     objects: Vec<Box<dyn Hittable>>,
 }
 
+impl Default for HittableList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HittableList {
     pub fn new() -> Self {
         Self {
@@ -37,4 +44,10 @@ impl Hittable for HittableList {
 
         hit_anything
     }
+
+    fn collect_lights(&self, lights: &mut Vec<Light>) {
+        for object in self.objects.iter() {
+            object.collect_lights(lights);
+        }
+    }
 }
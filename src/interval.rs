@@ -23,6 +23,14 @@ impl Interval {
         }
     }
 
+    /// Smallest interval containing both `a` and `b`.
+    pub fn enclosing(a: &Interval, b: &Interval) -> Self {
+        Self {
+            min: a.min.min(b.min),
+            max: a.max.max(b.max),
+        }
+    }
+
     pub fn size(&self) -> f64 {
         self.max - self.min
     }
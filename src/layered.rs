@@ -0,0 +1,244 @@
+use crate::bababoi::random_double;
+use crate::color::luminance;
+use crate::fresnel;
+use crate::hittable::HitRecord;
+use crate::material::Material;
+use crate::medium::Medium;
+use crate::microfacet::TrowbridgeReitz;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::vec3::{Color, Vec3};
+use std::sync::Arc;
+
+/// A dielectric coat over any base material, such as varnish on wood or the
+/// clearcoat of car paint.
+///
+/// The coat reflects with a GGX lobe and Fresnel weight. What it lets through
+/// reaches the base, is tinted on the way in and out, and has to cross the
+/// coat again to leave, so the layers never reflect more than arrives. Rays
+/// hitting the inside of the base skip the coat.
+pub struct Coated {
+    base: Arc<dyn Material>,
+    ior: f64,
+    distribution: TrowbridgeReitz,
+    /// Color left by one trip down to the base and back at normal incidence.
+    tint: Color,
+}
+
+impl Coated {
+    pub fn new(base: Arc<dyn Material>, ior: f64, roughness: f64, tint: Color) -> Arc<Self> {
+        Arc::new(Self {
+            base,
+            ior,
+            distribution: TrowbridgeReitz::new((roughness * roughness).max(1e-3)),
+            tint,
+        })
+    }
+
+    /// Colorless coat with the index of refraction of common lacquers.
+    pub fn clear(base: Arc<dyn Material>, roughness: f64) -> Arc<Self> {
+        Self::new(base, 1.5, roughness, Color::new(1.0, 1.0, 1.0))
+    }
+
+    /// Coat reflection times cosine for local directions `wo` and `wi`.
+    fn coat_eval(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let wm = (*wo + *wi).unit_vector();
+        let f = fresnel::dielectric(wo.dot(&wm).abs(), self.ior);
+        f * self.distribution.d(&wm) * self.distribution.g(wo, wi) / (4.0 * wo.z())
+    }
+
+    fn coat_pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let wm = (*wo + *wi).unit_vector();
+        self.distribution.pdf(wo, &wm) / (4.0 * wo.dot(&wm).abs())
+    }
+
+    /// Share of the base's response that makes it through the coat. Light
+    /// transmitted into the base crosses the coat only once.
+    fn base_factor(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        let cos_o = wo.z().max(1e-4);
+        let cos_i = wi.z().abs().max(1e-4);
+        let entering = 1.0 - fresnel::dielectric(cos_o, self.ior);
+        let (crossings, path) = if wi.z() > 0.0 {
+            (
+                entering * (1.0 - fresnel::dielectric(cos_i, self.ior)),
+                0.5 / cos_o + 0.5 / cos_i,
+            )
+        } else {
+            (entering, 0.5 / cos_o)
+        };
+        Color::new(
+            self.tint.x().powf(path),
+            self.tint.y().powf(path),
+            self.tint.z().powf(path),
+        ) * crossings
+    }
+
+    /// Chance of sampling the coat rather than the base, following the
+    /// Fresnel split of the incoming light.
+    fn coat_probability(&self, cos_o: f64) -> f64 {
+        let f = fresnel::dielectric(cos_o, self.ior);
+        let through = (1.0 - f) * luminance(self.tint);
+        (f / (f + through).max(1e-6)).clamp(0.05, 0.95)
+    }
+
+    fn local_directions(r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> (Vec3, Vec3) {
+        let frame = Onb::new(&rec.normal);
+        (
+            frame.to_local(&-r_in.direction().unit_vector()),
+            frame.to_local(&direction.unit_vector()),
+        )
+    }
+}
+
+impl Material for Coated {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let (weight, scattered, _) = self.scatter_lobe(r_in, rec)?;
+        Some((weight, scattered))
+    }
+
+    /// The coat lobe is never delta, so it is light sampled even over a delta
+    /// base. Only samples taken from such a base are reported as delta.
+    fn scatter_lobe(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray, bool)> {
+        if !rec.front_face {
+            return self.base.scatter_lobe(r_in, rec);
+        }
+
+        let frame = Onb::new(&rec.normal);
+        let wo = frame.to_local(&-r_in.direction().unit_vector());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let p_coat = self.coat_probability(wo.z());
+
+        let scattered = if random_double() < p_coat {
+            let wm = self
+                .distribution
+                .sample_wm(&wo, random_double(), random_double());
+            let wi = (-wo).reflect(&wm);
+            if wi.z() <= 0.0 {
+                return None;
+            }
+            Ray::new(rec.p, frame.transform(&wi))
+        } else {
+            let (base_weight, scattered, delta) = self.base.scatter_lobe(r_in, rec)?;
+            if delta {
+                // A delta direction has no density to weigh against the coat,
+                // so it is weighted by the branch taken alone.
+                let wi = frame.to_local(&scattered.direction().unit_vector());
+                let weight = self.base_factor(&wo, &wi) * base_weight / (1.0 - p_coat);
+                return Some((weight, scattered, true));
+            }
+            scattered
+        };
+
+        let pdf = self.pdf(r_in, rec, &scattered.direction());
+        if pdf <= 0.0 {
+            return None;
+        }
+        let weight = self.eval(r_in, rec, &scattered.direction()) / pdf;
+        Some((weight, scattered, false))
+    }
+
+    /// Over a delta base only the coat contributes, since the base's `eval`
+    /// and `pdf` are zero.
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        if !rec.front_face {
+            return self.base.eval(r_in, rec, direction);
+        }
+        let (wo, wi) = Self::local_directions(r_in, rec, direction);
+        let coat = self.coat_eval(&wo, &wi);
+        Color::new(coat, coat, coat)
+            + self.base_factor(&wo, &wi) * self.base.eval(r_in, rec, direction)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        if !rec.front_face {
+            return self.base.pdf(r_in, rec, direction);
+        }
+        let (wo, wi) = Self::local_directions(r_in, rec, direction);
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+        let p_coat = self.coat_probability(wo.z());
+        p_coat * self.coat_pdf(&wo, &wi) + (1.0 - p_coat) * self.base.pdf(r_in, rec, direction)
+    }
+
+    fn interior_medium(&self) -> Option<Medium> {
+        self.base.interior_medium()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, Metal};
+    use crate::vec3::Point3;
+
+    fn average_weight(mat: Arc<dyn Material>, check_pdf: bool) -> Color {
+        let r_in = Ray::new(Point3::new(-1.0, 2.0, 0.5), Vec3::new(1.0, -2.0, -0.5));
+        let mut rec = HitRecord::new(Point3::zero(), Vec3::zero(), mat.clone(), 1.0);
+        rec.set_face_normal(&r_in, Vec3::new(0.0, 1.0, 0.0));
+
+        let n = 20000;
+        let mut total = Color::zero();
+        for _ in 0..n {
+            if let Some((weight, scattered, delta)) = mat.scatter_lobe(&r_in, &rec) {
+                if check_pdf && !delta {
+                    let pdf = mat.pdf(&r_in, &rec, &scattered.direction());
+                    let expected = mat.eval(&r_in, &rec, &scattered.direction()) / pdf;
+                    assert!((weight - expected).length() < 1e-6 * (1.0 + weight.length()));
+                }
+                total += weight;
+            }
+        }
+        total / n as f64
+    }
+
+    #[test]
+    fn test_coated_diffuse_conserves_energy() {
+        let white = Lambertian::new(Color::new(1.0, 1.0, 1.0));
+        let average = average_weight(Coated::clear(white, 0.3), true);
+        assert!(average.y() <= 1.02 && average.y() > 0.8);
+    }
+
+    #[test]
+    fn test_coated_mirror_conserves_energy() {
+        let mirror = Metal::new(Color::new(1.0, 1.0, 1.0), 0.0);
+        let mat = Coated::clear(mirror, 0.1);
+        assert!(!mat.is_delta());
+        let average = average_weight(mat, true);
+        assert!(average.y() <= 1.02 && average.y() > 0.8);
+    }
+
+    #[test]
+    fn test_coat_over_mirror_is_light_sampled() {
+        let mirror = Metal::new(Color::new(1.0, 1.0, 1.0), 0.0);
+        let mat = Coated::clear(mirror, 0.3);
+        let r_in = Ray::new(Point3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
+        let mut rec = HitRecord::new(Point3::zero(), Vec3::zero(), mat.clone(), 1.0);
+        rec.set_face_normal(&r_in, Vec3::new(0.0, 1.0, 0.0));
+
+        // Just off the mirror direction, only the rough coat reflects.
+        let direction = Vec3::new(1.0, 1.1, 0.1);
+        assert!(mat.eval(&r_in, &rec, &direction).y() > 0.0);
+        assert!(mat.pdf(&r_in, &rec, &direction) > 0.0);
+    }
+
+    #[test]
+    fn test_tint_darkens_base() {
+        let white = Lambertian::new(Color::new(1.0, 1.0, 1.0));
+        let mat = Coated::new(white, 1.5, 0.3, Color::new(1.0, 0.2, 0.2));
+        let average = average_weight(mat, true);
+        assert!(average.x() > average.y() + 0.2);
+    }
+}
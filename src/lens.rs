@@ -0,0 +1,399 @@
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// One surface of a lens prescription, with the medium behind it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LensElement {
+    /// Radius of curvature in meters; positive when the center lies towards
+    /// the film, and zero for the aperture stop.
+    pub curvature_radius: f64,
+    /// Distance along the axis to the next surface, in meters.
+    pub thickness: f64,
+    /// Index of refraction behind the surface; zero or one for air.
+    pub ior: f64,
+    /// Radius of the clear aperture in meters.
+    pub aperture_radius: f64,
+}
+
+impl LensElement {
+    fn is_stop(&self) -> bool {
+        self.curvature_radius == 0.0
+    }
+
+    fn eta(&self) -> f64 {
+        if self.ior == 0.0 {
+            1.0
+        } else {
+            self.ior
+        }
+    }
+}
+
+/// A sequence of spherical lens elements, from the front (scene side) to the
+/// rear (film side), traced exactly as in pbrt's realistic camera (Kolb et
+/// al. 1995). Rays that hit a barrel or stop are lost, so vignetting,
+/// distortion and focus fall out of the optics.
+///
+/// Positions use camera space in meters: the film lies at z = 0 and the
+/// scene towards positive z.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LensSystem {
+    elements: Vec<LensElement>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Refracts unit `wi`, pointing away from the surface on the side of
+/// `normal`, with `eta` the incident over transmitted index.
+fn refract(wi: &Vec3, normal: &Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = normal.dot(wi);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i).max(0.0);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-*wi * eta + *normal * (eta * cos_i - cos_t))
+}
+
+/// Converts between camera space and lens space, which has z flipped so
+/// the elements lie at negative z.
+fn flip(ray: &Ray) -> Ray {
+    let o = ray.origin();
+    let d = ray.direction();
+    Ray::new(
+        Point3::new(o.x(), o.y(), -o.z()),
+        Vec3::new(d.x(), d.y(), -d.z()),
+    )
+}
+
+impl LensSystem {
+    /// Fails if there are no elements, since focusing and tracing need at
+    /// least a front and rear surface.
+    pub fn new(elements: Vec<LensElement>) -> io::Result<Self> {
+        if elements.is_empty() {
+            return Err(invalid("lens prescription has no elements".to_string()));
+        }
+        Ok(Self { elements })
+    }
+
+    /// Reads a prescription in the format of pbrt's lens files: one surface
+    /// per line giving curvature radius, thickness, index of refraction and
+    /// aperture diameter, all lengths in millimeters. `#` starts a comment.
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut elements = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let values: Vec<f64> = line
+                .split_whitespace()
+                .map(|v| v.parse::<f64>())
+                .collect::<Result<_, _>>()
+                .map_err(|_| invalid(format!("bad number on lens line {}", number + 1)))?;
+            if values.len() != 4 {
+                return Err(invalid(format!(
+                    "lens line {} needs 4 values, found {}",
+                    number + 1,
+                    values.len()
+                )));
+            }
+            elements.push(LensElement {
+                curvature_radius: values[0] * 0.001,
+                thickness: values[1] * 0.001,
+                ior: values[2],
+                aperture_radius: values[3] * 0.001 / 2.0,
+            });
+        }
+        Self::new(elements)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// A 50mm f/2 double-Gauss lens (US patent 2,673,491, scaled from
+    /// 100mm), a common photographic design.
+    pub fn double_gauss_50mm() -> Self {
+        Self::parse(
+            "29.475  3.76   1.67   25.2
+             84.83   0.12   1      25.2
+             19.275  4.025  1.67   23
+             40.77   3.275  1.699  23
+             12.75   5.705  1      18
+             0       4.5    0      17.1
+             -14.495 1.18   1.603  17
+             40.77   6.065  1.658  20
+             -20.385 0.19   1      20
+             437.065 3.22   1.717  20
+             -39.73  0      1      20",
+        )
+        .expect("built-in lens prescription is valid")
+    }
+
+    pub fn elements(&self) -> &[LensElement] {
+        &self.elements
+    }
+
+    /// Distance from the film to the rear surface, the last element's
+    /// thickness.
+    pub fn rear_z(&self) -> f64 {
+        self.elements.last().map_or(0.0, |e| e.thickness)
+    }
+
+    /// Distance from the film to the front surface.
+    pub fn front_z(&self) -> f64 {
+        self.elements.iter().map(|e| e.thickness).sum()
+    }
+
+    pub fn rear_aperture_radius(&self) -> f64 {
+        self.elements.last().map_or(0.0, |e| e.aperture_radius)
+    }
+
+    /// The same lens with the film `film_distance` behind the rear surface.
+    pub fn with_film_distance(&self, film_distance: f64) -> Self {
+        let mut elements = self.elements.clone();
+        if let Some(last) = elements.last_mut() {
+            last.thickness = film_distance;
+        }
+        Self { elements }
+    }
+
+    /// Finds where a ray meets element `element` whose vertex is at
+    /// `element_z` in lens space, returning the distance and the surface
+    /// normal facing the ray.
+    fn intersect(element: &LensElement, element_z: f64, ray: &Ray) -> Option<(f64, Vec3)> {
+        let d = ray.direction();
+        if element.is_stop() {
+            if d.z() == 0.0 {
+                return None;
+            }
+            let t = (element_z - ray.origin().z()) / d.z();
+            return Some((t, Vec3::new(0.0, 0.0, -d.z().signum())));
+        }
+
+        let radius = element.curvature_radius;
+        let o = ray.origin() - Vec3::new(0.0, 0.0, element_z + radius);
+        let a = d.length_squared();
+        let b = 2.0 * d.dot(&o);
+        let c = o.length_squared() - radius * radius;
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let sqrtd = discriminant.sqrt();
+        let t0 = (-b - sqrtd) / (2.0 * a);
+        let t1 = (-b + sqrtd) / (2.0 * a);
+
+        // Pick the hit on the part of the sphere that forms the lens surface.
+        let use_closer = (d.z() > 0.0) ^ (radius < 0.0);
+        let t = if use_closer { t0.min(t1) } else { t0.max(t1) };
+        if t < 0.0 {
+            return None;
+        }
+        let n = (o + d * t).unit_vector();
+        let n = if n.dot(&-d) < 0.0 { -n } else { n };
+        Some((t, n))
+    }
+
+    /// Traces a camera-space ray leaving the film out through the lens.
+    /// Returns `None` if the lens blocks it.
+    pub fn trace_from_film(&self, ray: &Ray) -> Option<Ray> {
+        let mut ray = flip(ray);
+        let mut element_z = 0.0;
+        for i in (0..self.elements.len()).rev() {
+            let element = &self.elements[i];
+            element_z -= element.thickness;
+
+            let (t, n) = Self::intersect(element, element_z, &ray)?;
+            let p = ray.at(t);
+            if p.x() * p.x() + p.y() * p.y() > element.aperture_radius * element.aperture_radius {
+                return None;
+            }
+
+            let direction = if element.is_stop() {
+                ray.direction()
+            } else {
+                let eta_t = if i > 0 {
+                    self.elements[i - 1].eta()
+                } else {
+                    1.0
+                };
+                refract(&-ray.direction().unit_vector(), &n, element.eta() / eta_t)?
+            };
+            ray = Ray::new(p, direction);
+        }
+        Some(flip(&ray))
+    }
+
+    /// Traces a camera-space ray arriving from the scene in through the lens
+    /// towards the film.
+    pub fn trace_from_scene(&self, ray: &Ray) -> Option<Ray> {
+        let mut ray = flip(ray);
+        let mut element_z = -self.front_z();
+        for (i, element) in self.elements.iter().enumerate() {
+            let (t, n) = Self::intersect(element, element_z, &ray)?;
+            let p = ray.at(t);
+            if p.x() * p.x() + p.y() * p.y() > element.aperture_radius * element.aperture_radius {
+                return None;
+            }
+
+            let direction = if element.is_stop() {
+                ray.direction()
+            } else {
+                let eta_i = if i > 0 {
+                    self.elements[i - 1].eta()
+                } else {
+                    1.0
+                };
+                refract(&-ray.direction().unit_vector(), &n, eta_i / element.eta())?
+            };
+            ray = Ray::new(p, direction);
+            element_z += element.thickness;
+        }
+        Some(flip(&ray))
+    }
+
+    /// Principal plane and focal point along z of a ray that entered parallel
+    /// to the axis and left as `exiting`.
+    fn cardinal_points(entering: &Ray, exiting: &Ray) -> (f64, f64) {
+        let tf = -exiting.origin().x() / exiting.direction().x();
+        let focal_z = exiting.at(tf).z();
+        let tp = (entering.origin().x() - exiting.origin().x()) / exiting.direction().x();
+        let principal_z = exiting.at(tp).z();
+        (principal_z, focal_z)
+    }
+
+    /// Principal planes and focal points for light from the scene side and
+    /// from the film side, as `[(principal, focal); 2]`.
+    fn thick_lens(&self) -> Option<[(f64, f64); 2]> {
+        // Paraxial rays close to the axis.
+        let x = 0.1
+            * self
+                .rear_aperture_radius()
+                .min(self.elements[0].aperture_radius);
+
+        let from_scene = Ray::new(
+            Point3::new(x, 0.0, self.front_z() + 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+        let to_film = self.trace_from_scene(&from_scene)?;
+
+        let from_film = Ray::new(
+            Point3::new(x, 0.0, self.rear_z() - 1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        let to_scene = self.trace_from_film(&from_film)?;
+
+        Some([
+            Self::cardinal_points(&from_scene, &to_film),
+            Self::cardinal_points(&from_film, &to_scene),
+        ])
+    }
+
+    /// Effective focal length in meters, from the thick lens approximation.
+    pub fn focal_length(&self) -> Option<f64> {
+        let [(principal, focal), _] = self.thick_lens()?;
+        Some(principal - focal)
+    }
+
+    /// Distance from the rear surface to the film that brings objects at
+    /// `focus_distance` meters in front of the film into focus.
+    pub fn focus(&self, focus_distance: f64) -> Option<f64> {
+        let [(p0, f0), (p1, _)] = self.thick_lens()?;
+        // With the film at z = 0, image and object distances are measured
+        // from the principal planes.
+        let f = p0 - f0;
+        let z = -focus_distance;
+        let p0 = -p0;
+        let p1 = -p1;
+        let c = (p1 - z - p0) * (p1 - z - 4.0 * f - p0);
+        if c <= 0.0 {
+            return None;
+        }
+        let delta = 0.5 * (p1 - z + p0 - c.sqrt());
+        Some(self.rear_z() + delta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_prescription() {
+        let lens = LensSystem::parse(
+            "# radius thickness ior aperture\n50 5 1.5 20\n0 3 0 10\n-50 40 1 20\n",
+        )
+        .unwrap();
+        assert_eq!(lens.elements().len(), 3);
+        assert!((lens.elements()[0].aperture_radius - 0.01).abs() < 1e-12);
+        assert!((lens.front_z() - 0.048).abs() < 1e-12);
+        assert!(LensSystem::parse("50 5 1.5").is_err());
+        assert!(LensSystem::parse("# no surfaces\n").is_err());
+        assert!(LensSystem::new(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_double_gauss_focal_length() {
+        let lens = LensSystem::double_gauss_50mm();
+        let f = lens.focal_length().unwrap();
+        assert!((f - 0.05).abs() < 0.003, "focal length {f}");
+    }
+
+    #[test]
+    fn test_focused_point_images_sharply() {
+        let focus_distance = 2.0;
+        let base = LensSystem::double_gauss_50mm();
+        let lens = base.with_film_distance(base.focus(focus_distance).unwrap());
+
+        // Rays from an on-axis point at the focus distance converge on the
+        // film center.
+        let object = Point3::new(0.0, 0.0, focus_distance);
+        let front_radius = lens.elements()[0].aperture_radius;
+        let mut hits = 0;
+        for k in 1..8 {
+            let target = Point3::new(front_radius * k as f64 / 16.0, 0.0, lens.front_z());
+            let ray = Ray::new(object, target - object);
+            if let Some(out) = lens.trace_from_scene(&ray) {
+                let t = -out.origin().z() / out.direction().z();
+                assert!(out.at(t).x().abs() < 5e-5, "blur {}", out.at(t).x());
+                hits += 1;
+            }
+        }
+        assert!(hits >= 4);
+    }
+
+    #[test]
+    fn test_off_axis_rays_vignette() {
+        let base = LensSystem::double_gauss_50mm();
+        let lens = base.with_film_distance(base.focus(5.0).unwrap());
+        let rear = lens.rear_z();
+        let pupil = lens.rear_aperture_radius();
+
+        let passing = |film_x: f64| {
+            let n = 40;
+            let mut count = 0;
+            for i in 0..n {
+                for j in 0..n {
+                    let px = pupil * (2.0 * (i as f64 + 0.5) / n as f64 - 1.0);
+                    let py = pupil * (2.0 * (j as f64 + 0.5) / n as f64 - 1.0);
+                    let from = Point3::new(film_x, 0.0, 0.0);
+                    let ray = Ray::new(from, Point3::new(px, py, rear) - from);
+                    if lens.trace_from_film(&ray).is_some() {
+                        count += 1;
+                    }
+                }
+            }
+            count
+        };
+        let center = passing(0.0);
+        assert!(center > 0);
+        assert!(passing(0.02) < center);
+    }
+}
@@ -1,11 +1,37 @@
+pub mod aabb;
+pub mod adaptive;
+pub mod alpha_mask;
+pub mod aperture;
 pub mod bababoi;
 pub mod camera;
+pub mod checkpoint;
 pub mod color;
+#[cfg(feature = "gpu")]
 pub mod gpu;
+pub mod film;
+pub mod filter;
+pub mod fresnel;
 pub mod hittable;
 pub mod hittable_list;
 pub mod interval;
+pub mod layered;
+pub mod lens;
+pub mod light;
+pub mod light_bvh;
 pub mod material;
+pub mod medium;
+pub mod microfacet;
+pub mod mix;
+pub mod onb;
+pub mod ppm;
+pub mod principled;
+pub mod progressive;
+pub mod quad;
 pub mod ray;
+pub mod sampler;
+pub mod sky;
+pub mod spectrum;
 pub mod sphere;
+pub mod texture;
+pub mod thin_film;
 pub mod vec3;
@@ -0,0 +1,630 @@
+use crate::aabb::Aabb;
+use crate::bababoi::random_double;
+use crate::color::luminance;
+use crate::light_bvh::BvhLightSampler;
+use crate::onb::Onb;
+use crate::vec3::{Color, Point3, Vec3};
+use std::f64::consts::PI;
+
+/// An emissive sphere that can be sampled directly from a shading point.
+#[derive(Debug, Clone, Copy)]
+pub struct SphereLight {
+    center: Point3,
+    radius: f64,
+    power: f64,
+}
+
+impl SphereLight {
+    /// Creates a light for a sphere whose surface emits `radiance`.
+    pub fn new(center: Point3, radius: f64, radiance: Color) -> Self {
+        let area = 4.0 * PI * radius * radius;
+        Self {
+            center,
+            radius,
+            power: luminance(radiance) * PI * area,
+        }
+    }
+
+    pub fn center(&self) -> Point3 {
+        self.center
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    /// Total emitted power, measured as luminance.
+    pub fn power(&self) -> f64 {
+        self.power
+    }
+
+    pub fn bounding_box(&self) -> Aabb {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        Aabb::from_points(self.center - r, self.center + r)
+    }
+
+    /// A sphere's normals point every way.
+    pub fn emission_cone(&self) -> EmissionCone {
+        EmissionCone::everywhere()
+    }
+
+    /// Samples a direction from `origin` towards the visible cap of the sphere,
+    /// uniformly over the subtended solid angle.
+    pub fn sample(&self, origin: Point3) -> Option<Vec3> {
+        let cos_theta_max = self.cos_theta_max(origin)?;
+        Some(sample_cone(&(self.center - origin), cos_theta_max))
+    }
+
+    /// Solid angle density of `sample` for the given direction.
+    pub fn pdf(&self, origin: Point3, direction: &Vec3) -> f64 {
+        let cos_theta_max = match self.cos_theta_max(origin) {
+            Some(c) => c,
+            None => return 0.0,
+        };
+
+        let to_center = (self.center - origin).unit_vector();
+        if direction.unit_vector().dot(&to_center) < cos_theta_max {
+            return 0.0;
+        }
+
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+        1.0 / solid_angle
+    }
+
+    fn cos_theta_max(&self, origin: Point3) -> Option<f64> {
+        let distance_squared = (self.center - origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return None;
+        }
+        Some((1.0 - radius_squared / distance_squared).sqrt())
+    }
+}
+
+/// An emissive parallelogram spanned by edges `u` and `v` from corner `q`.
+/// Its front, the side `u × v` points to, and its back emit separately, and
+/// either may be dark.
+#[derive(Debug, Clone, Copy)]
+pub struct QuadLight {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    normal: Vec3,
+    area: f64,
+    front: Color,
+    back: Color,
+    power: f64,
+}
+
+impl QuadLight {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, front: Color, back: Color) -> Self {
+        let n = u.cross(&v);
+        let area = n.length();
+        Self {
+            q,
+            u,
+            v,
+            normal: n / area,
+            area,
+            front,
+            back,
+            power: (luminance(front) + luminance(back)) * PI * area,
+        }
+    }
+
+    pub fn center(&self) -> Point3 {
+        self.q + (self.u + self.v) * 0.5
+    }
+
+    /// Total emitted power, measured as luminance.
+    pub fn power(&self) -> f64 {
+        self.power
+    }
+
+    /// Box around the quad, padded so it has some thickness along every axis.
+    pub fn bounding_box(&self) -> Aabb {
+        let pad = Vec3::new(1e-4, 1e-4, 1e-4);
+        let a = Aabb::from_points(self.q, self.q + self.u + self.v);
+        let b = Aabb::from_points(self.q + self.u, self.q + self.v);
+        let bounds = Aabb::surrounding(&a, &b);
+        let corner = |x: f64, y: f64, z: f64| Point3::new(x, y, z);
+        Aabb::from_points(
+            corner(bounds.x.min, bounds.y.min, bounds.z.min) - pad,
+            corner(bounds.x.max, bounds.y.max, bounds.z.max) + pad,
+        )
+    }
+
+    pub fn emission_cone(&self) -> EmissionCone {
+        let (front, back) = (luminance(self.front) > 0.0, luminance(self.back) > 0.0);
+        EmissionCone {
+            axis: if front { self.normal } else { -self.normal },
+            cos_theta: 1.0,
+            two_sided: front && back,
+        }
+    }
+
+    /// Samples a direction from `origin` towards a point chosen uniformly on
+    /// the quad, or `None` if `origin` can't see its emitting side.
+    pub fn sample(&self, origin: Point3) -> Option<Vec3> {
+        if !self.faces(origin) {
+            return None;
+        }
+        let point = self.q + self.u * random_double() + self.v * random_double();
+        Some(point - origin)
+    }
+
+    /// Solid angle density of `sample` for the given direction.
+    pub fn pdf(&self, origin: Point3, direction: &Vec3) -> f64 {
+        if !self.faces(origin) {
+            return 0.0;
+        }
+        let denom = self.normal.dot(direction);
+        if denom.abs() < 1e-12 {
+            return 0.0;
+        }
+        let t = self.normal.dot(&(self.q - origin)) / denom;
+        if t <= 0.0 {
+            return 0.0;
+        }
+
+        // Position of the hit in the quad's edge coordinates.
+        let hit = origin + *direction * t - self.q;
+        let w = self.u.cross(&self.v) / (self.area * self.area);
+        let alpha = w.dot(&hit.cross(&self.v));
+        let beta = w.dot(&self.u.cross(&hit));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return 0.0;
+        }
+
+        let distance_squared = (*direction * t).length_squared();
+        let cosine = (denom / direction.length()).abs();
+        distance_squared / (cosine * self.area)
+    }
+
+    /// Whether `origin` lies on a side of the plane that the quad emits to.
+    fn faces(&self, origin: Point3) -> bool {
+        let side = self.normal.dot(&(origin - self.q));
+        let radiance = if side > 0.0 {
+            self.front
+        } else if side < 0.0 {
+            self.back
+        } else {
+            return false;
+        };
+        luminance(radiance) > 0.0
+    }
+}
+
+/// Bound on the normals of emitting surfaces: all lie within
+/// `acos(cos_theta)` of `axis`, or of `-axis` as well when two-sided. Every
+/// emitter is diffuse, so each normal lights the hemisphere around it.
+#[derive(Debug, Clone, Copy)]
+pub struct EmissionCone {
+    pub axis: Vec3,
+    pub cos_theta: f64,
+    pub two_sided: bool,
+}
+
+impl EmissionCone {
+    pub fn everywhere() -> Self {
+        Self {
+            axis: Vec3::new(0.0, 0.0, 1.0),
+            cos_theta: -1.0,
+            two_sided: false,
+        }
+    }
+
+    /// Smallest cone holding both `a` and `b` (after pbrt-v4's
+    /// `DirectionCone::Union`).
+    pub fn surrounding(a: &EmissionCone, b: &EmissionCone) -> Self {
+        let two_sided = a.two_sided || b.two_sided;
+        let theta_a = a.cos_theta.clamp(-1.0, 1.0).acos();
+        let theta_b = b.cos_theta.clamp(-1.0, 1.0).acos();
+        let theta_d = a.axis.dot(&b.axis).clamp(-1.0, 1.0).acos();
+        if (theta_d + theta_b).min(PI) <= theta_a {
+            return Self { two_sided, ..*a };
+        }
+        if (theta_d + theta_a).min(PI) <= theta_b {
+            return Self { two_sided, ..*b };
+        }
+
+        let theta_o = (theta_a + theta_d + theta_b) * 0.5;
+        let rotation_axis = a.axis.cross(&b.axis);
+        if theta_o >= PI || rotation_axis.near_zero() {
+            return Self {
+                two_sided,
+                ..Self::everywhere()
+            };
+        }
+
+        // Turn `a`'s axis towards `b`'s until the cone just holds both.
+        let k = rotation_axis.unit_vector();
+        let (sin_r, cos_r) = (theta_o - theta_a).sin_cos();
+        let axis = a.axis * cos_r + k.cross(&a.axis) * sin_r;
+        Self {
+            axis: axis.unit_vector(),
+            cos_theta: theta_o.cos(),
+            two_sided,
+        }
+    }
+}
+
+/// Emissive geometry that next event estimation can sample directly.
+#[derive(Debug, Clone, Copy)]
+pub enum Light {
+    Sphere(SphereLight),
+    Quad(QuadLight),
+}
+
+impl From<SphereLight> for Light {
+    fn from(light: SphereLight) -> Self {
+        Light::Sphere(light)
+    }
+}
+
+impl From<QuadLight> for Light {
+    fn from(light: QuadLight) -> Self {
+        Light::Quad(light)
+    }
+}
+
+impl Light {
+    pub fn center(&self) -> Point3 {
+        match self {
+            Light::Sphere(light) => light.center(),
+            Light::Quad(light) => light.center(),
+        }
+    }
+
+    /// Total emitted power, measured as luminance.
+    pub fn power(&self) -> f64 {
+        match self {
+            Light::Sphere(light) => light.power(),
+            Light::Quad(light) => light.power(),
+        }
+    }
+
+    pub fn bounding_box(&self) -> Aabb {
+        match self {
+            Light::Sphere(light) => light.bounding_box(),
+            Light::Quad(light) => light.bounding_box(),
+        }
+    }
+
+    pub fn emission_cone(&self) -> EmissionCone {
+        match self {
+            Light::Sphere(light) => light.emission_cone(),
+            Light::Quad(light) => light.emission_cone(),
+        }
+    }
+
+    /// Scales the power the light is picked by, for emitters that are only
+    /// partly visible.
+    pub fn scale_power(&mut self, factor: f64) {
+        match self {
+            Light::Sphere(light) => light.power *= factor,
+            Light::Quad(light) => light.power *= factor,
+        }
+    }
+
+    /// Samples a direction from `origin` towards the light.
+    pub fn sample(&self, origin: Point3) -> Option<Vec3> {
+        match self {
+            Light::Sphere(light) => light.sample(origin),
+            Light::Quad(light) => light.sample(origin),
+        }
+    }
+
+    /// Solid angle density of `sample` for the given direction.
+    pub fn pdf(&self, origin: Point3, direction: &Vec3) -> f64 {
+        match self {
+            Light::Sphere(light) => light.pdf(origin, direction),
+            Light::Quad(light) => light.pdf(origin, direction),
+        }
+    }
+}
+
+/// The sun as a disk at infinity, seen from the ground. Its radiance is
+/// sampled directly rather than by hitting geometry, so the shadow ray must
+/// escape the scene.
+#[derive(Debug, Clone, Copy)]
+pub struct SunLight {
+    direction: Vec3,
+    cos_theta_max: f64,
+    radiance: Color,
+}
+
+impl SunLight {
+    /// Creates a sun towards `direction` whose disk is `angular_radius`
+    /// degrees across and emits `radiance`.
+    pub fn new(direction: Vec3, angular_radius: f64, radiance: Color) -> Self {
+        Self {
+            direction: direction.unit_vector(),
+            cos_theta_max: angular_radius.to_radians().cos(),
+            radiance,
+        }
+    }
+
+    /// Radiance arriving from `direction`: the sun's inside its disk and
+    /// above the horizon, zero elsewhere.
+    pub fn radiance(&self, direction: &Vec3) -> Color {
+        let unit_direction = direction.unit_vector();
+        if unit_direction.y() >= 0.0 && unit_direction.dot(&self.direction) >= self.cos_theta_max
+        {
+            self.radiance
+        } else {
+            Color::zero()
+        }
+    }
+
+    /// Samples a direction towards the disk, uniformly over its solid angle.
+    pub fn sample(&self) -> Vec3 {
+        sample_cone(&self.direction, self.cos_theta_max)
+    }
+
+    /// Solid angle density of `sample` for the given direction.
+    pub fn pdf(&self, direction: &Vec3) -> f64 {
+        if direction.unit_vector().dot(&self.direction) < self.cos_theta_max {
+            return 0.0;
+        }
+        1.0 / (2.0 * PI * (1.0 - self.cos_theta_max))
+    }
+}
+
+/// Direction drawn uniformly from the cone of half-angle `acos(cos_theta_max)`
+/// around `axis`.
+fn sample_cone(axis: &Vec3, cos_theta_max: f64) -> Vec3 {
+    let r1 = random_double();
+    let r2 = random_double();
+    let z = 1.0 + r2 * (cos_theta_max - 1.0);
+    let phi = 2.0 * PI * r1;
+    let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+
+    let uvw = Onb::new(axis);
+    uvw.transform(&Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z))
+}
+
+/// Strategy for choosing which light to sample at a shading point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightSampling {
+    /// Every light is equally likely.
+    Uniform,
+    /// Lights are chosen in proportion to their emitted power.
+    Power,
+    /// Lights are chosen by walking a light BVH, estimating each cluster's
+    /// contribution to the shading point.
+    Bvh,
+}
+
+impl LightSampling {
+    pub fn build(self, lights: Vec<Light>) -> Box<dyn LightSampler> {
+        match self {
+            LightSampling::Uniform => Box::new(UniformLightSampler::new(lights)),
+            LightSampling::Power => Box::new(PowerLightSampler::new(lights)),
+            LightSampling::Bvh => Box::new(BvhLightSampler::new(lights)),
+        }
+    }
+}
+
+/// Chooses lights for next event estimation.
+///
+/// `p` is the shading point and `n` its surface normal; pass a zero normal
+/// for points that are not on a surface.
+pub trait LightSampler: Send + Sync {
+    fn lights(&self) -> &[Light];
+
+    /// Picks a light, returning its index and the probability of picking it.
+    fn pick(&self, p: Point3, n: Vec3) -> Option<(usize, f64)>;
+
+    /// Probability that `pick` returns the light at `index`.
+    fn pmf(&self, p: Point3, n: Vec3, index: usize) -> f64;
+
+    fn is_empty(&self) -> bool {
+        self.lights().is_empty()
+    }
+
+    /// Samples a direction towards one of the lights.
+    fn sample(&self, p: Point3, n: Vec3) -> Option<Vec3> {
+        let (index, _) = self.pick(p, n)?;
+        self.lights()[index].sample(p)
+    }
+
+    /// Solid angle density of `sample` for the given direction, summed over
+    /// every light that could have produced it.
+    fn pdf(&self, p: Point3, n: Vec3, direction: &Vec3) -> f64 {
+        self.lights()
+            .iter()
+            .enumerate()
+            .map(|(i, light)| {
+                let light_pdf = light.pdf(p, direction);
+                if light_pdf > 0.0 {
+                    self.pmf(p, n, i) * light_pdf
+                } else {
+                    0.0
+                }
+            })
+            .sum()
+    }
+}
+
+pub struct UniformLightSampler {
+    lights: Vec<Light>,
+}
+
+impl UniformLightSampler {
+    pub fn new(lights: Vec<Light>) -> Self {
+        Self { lights }
+    }
+}
+
+impl LightSampler for UniformLightSampler {
+    fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    fn pick(&self, _p: Point3, _n: Vec3) -> Option<(usize, f64)> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let count = self.lights.len();
+        let index = ((random_double() * count as f64) as usize).min(count - 1);
+        Some((index, 1.0 / count as f64))
+    }
+
+    fn pmf(&self, _p: Point3, _n: Vec3, _index: usize) -> f64 {
+        if self.lights.is_empty() {
+            0.0
+        } else {
+            1.0 / self.lights.len() as f64
+        }
+    }
+}
+
+pub struct PowerLightSampler {
+    lights: Vec<Light>,
+    pmf: Vec<f64>,
+    cdf: Vec<f64>,
+}
+
+impl PowerLightSampler {
+    pub fn new(lights: Vec<Light>) -> Self {
+        let total: f64 = lights.iter().map(|l| l.power()).sum();
+        let pmf: Vec<f64> = if total > 0.0 {
+            lights.iter().map(|l| l.power() / total).collect()
+        } else {
+            vec![1.0 / lights.len().max(1) as f64; lights.len()]
+        };
+
+        let mut cdf = Vec::with_capacity(pmf.len());
+        let mut running = 0.0;
+        for p in &pmf {
+            running += p;
+            cdf.push(running);
+        }
+
+        Self { lights, pmf, cdf }
+    }
+}
+
+impl LightSampler for PowerLightSampler {
+    fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    fn pick(&self, _p: Point3, _n: Vec3) -> Option<(usize, f64)> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let u = random_double() * self.cdf[self.cdf.len() - 1];
+        let index = self
+            .cdf
+            .partition_point(|&c| c <= u)
+            .min(self.lights.len() - 1);
+        Some((index, self.pmf[index]))
+    }
+
+    fn pmf(&self, _p: Point3, _n: Vec3, index: usize) -> f64 {
+        self.pmf[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lights() -> Vec<Light> {
+        vec![
+            SphereLight::new(Point3::new(0.0, 5.0, 0.0), 1.0, Color::new(1.0, 1.0, 1.0)).into(),
+            SphereLight::new(Point3::new(5.0, 5.0, 0.0), 1.0, Color::new(3.0, 3.0, 3.0)).into(),
+        ]
+    }
+
+    #[test]
+    fn test_sphere_sample_is_inside_cone() {
+        let light = SphereLight::new(Point3::new(0.0, 0.0, -10.0), 2.0, Color::new(1.0, 1.0, 1.0));
+        let origin = Point3::zero();
+        for _ in 0..100 {
+            let dir = light.sample(origin).unwrap();
+            assert!(light.pdf(origin, &dir) > 0.0);
+        }
+        assert_eq!(light.pdf(origin, &Vec3::new(0.0, 0.0, 1.0)), 0.0);
+    }
+
+    #[test]
+    fn test_single_sided_quad_is_dark_behind() {
+        // A 2 by 2 quad at y = 4 facing down.
+        let light = QuadLight::new(
+            Point3::new(-1.0, 4.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            Color::new(1.0, 1.0, 1.0),
+            Color::zero(),
+        );
+        let below = Point3::zero();
+        for _ in 0..100 {
+            let dir = light.sample(below).unwrap();
+            assert!(light.pdf(below, &dir) > 0.0);
+        }
+        // Seen from straight below, the quad covers about area / distance².
+        let pdf = light.pdf(below, &Vec3::new(0.0, 1.0, 0.0));
+        assert!((pdf - 16.0 / 4.0).abs() < 1e-10);
+        assert_eq!(light.pdf(below, &Vec3::new(3.0, 1.0, 0.0)), 0.0);
+
+        let above = Point3::new(0.0, 8.0, 0.0);
+        assert!(light.sample(above).is_none());
+        assert_eq!(light.pdf(above, &Vec3::new(0.0, -1.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn test_emission_cones_surround_both() {
+        let cone = |axis: Vec3| EmissionCone {
+            axis,
+            cos_theta: 1.0,
+            two_sided: false,
+        };
+        let x = cone(Vec3::new(1.0, 0.0, 0.0));
+        let y = cone(Vec3::new(0.0, 1.0, 0.0));
+        let both = EmissionCone::surrounding(&x, &y);
+        assert!((both.cos_theta - (PI / 4.0).cos()).abs() < 1e-10);
+        for axis in [x.axis, y.axis] {
+            assert!(both.axis.dot(&axis) >= both.cos_theta - 1e-10);
+        }
+
+        let opposite = EmissionCone::surrounding(&x, &cone(-x.axis));
+        assert_eq!(opposite.cos_theta, -1.0);
+        let same = EmissionCone::surrounding(&both, &x);
+        assert_eq!(same.cos_theta, both.cos_theta);
+    }
+
+    #[test]
+    fn test_sun_samples_stay_in_disk() {
+        let sun = SunLight::new(Vec3::new(0.0, 1.0, -1.0), 0.5, Color::new(5.0, 5.0, 5.0));
+        for _ in 0..100 {
+            let dir = sun.sample();
+            assert!(sun.pdf(&dir) > 0.0);
+            assert_eq!(sun.radiance(&dir).x(), 5.0);
+        }
+        let away = Vec3::new(0.0, 1.0, 0.0);
+        assert_eq!(sun.pdf(&away), 0.0);
+        assert!(sun.radiance(&away).near_zero());
+    }
+
+    #[test]
+    fn test_power_pmf() {
+        let sampler = PowerLightSampler::new(lights());
+        let p = Point3::zero();
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        assert!((sampler.pmf(p, n, 0) - 0.25).abs() < 1e-10);
+        assert!((sampler.pmf(p, n, 1) - 0.75).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_uniform_pdf_matches_single_light() {
+        let sampler = UniformLightSampler::new(lights());
+        let p = Point3::zero();
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        let dir = Vec3::new(0.0, 1.0, 0.0);
+        let expected = 0.5 * sampler.lights()[0].pdf(p, &dir);
+        assert!((sampler.pdf(p, n, &dir) - expected).abs() < 1e-10);
+    }
+}
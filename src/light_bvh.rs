@@ -0,0 +1,358 @@
+use crate::aabb::Aabb;
+use crate::bababoi::random_double;
+use crate::light::{EmissionCone, Light, LightSampler};
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3};
+
+enum NodeKind {
+    Leaf { light: usize },
+    Interior { left: usize, right: usize },
+}
+
+struct LightNode {
+    bounds: Aabb,
+    cone: EmissionCone,
+    power: f64,
+    kind: NodeKind,
+}
+
+impl LightNode {
+    /// Conservative estimate of how much light from this cluster reaches a
+    /// point `p` with normal `n` (after Conty Estevez and Kulla 2018, as in
+    /// pbrt-v4's `LightBounds::Importance`).
+    fn importance(&self, p: Point3, n: Vec3) -> f64 {
+        if self.power <= 0.0 {
+            return 0.0;
+        }
+
+        let center = self.bounds.center();
+        let radius = self.bounds.diagonal().length() * 0.5;
+        let to_center = center - p;
+        let distance_squared = to_center.length_squared();
+        if distance_squared <= radius * radius {
+            return self.power / (radius * radius);
+        }
+
+        // Angle subtended by the cluster's bounding sphere.
+        let sin_bound = radius / distance_squared.sqrt();
+        let cos_bound = (1.0 - sin_bound * sin_bound).max(0.0).sqrt();
+
+        // Smallest angle between an emitting normal in the cluster and the
+        // direction to `p`; past a right angle no light leaves towards it.
+        let to_p = -to_center.unit_vector();
+        let mut cos_w = self.cone.axis.dot(&to_p);
+        if self.cone.two_sided {
+            cos_w = cos_w.abs();
+        }
+        let (cos_x, sin_x) = cos_sin_sub_clamped(cos_w, self.cone.cos_theta);
+        let (cos_emit, _) = cos_sin_sub_clamped_with(cos_x, sin_x, cos_bound, sin_bound);
+        if cos_emit <= 0.0 {
+            return 0.0;
+        }
+
+        // Smallest angle between the normal and any direction into the
+        // cluster, signed so clusters behind the surface get nothing.
+        let cos_receive = if n.near_zero() {
+            1.0
+        } else {
+            let cos_i = -to_p.dot(&n.unit_vector());
+            let sin_i = (1.0 - cos_i * cos_i).max(0.0).sqrt();
+            let (cos_receive, _) = cos_sin_sub_clamped_with(cos_i, sin_i, cos_bound, sin_bound);
+            cos_receive.max(0.0)
+        };
+
+        self.power * cos_emit * cos_receive / distance_squared
+    }
+}
+
+/// Cosine and sine of the angle `acos(cos_a) - acos(cos_b)`, clamped at zero.
+fn cos_sin_sub_clamped(cos_a: f64, cos_b: f64) -> (f64, f64) {
+    let sin = |cos: f64| (1.0 - cos * cos).max(0.0).sqrt();
+    cos_sin_sub_clamped_with(cos_a, sin(cos_a), cos_b, sin(cos_b))
+}
+
+fn cos_sin_sub_clamped_with(cos_a: f64, sin_a: f64, cos_b: f64, sin_b: f64) -> (f64, f64) {
+    if cos_a > cos_b {
+        (1.0, 0.0)
+    } else {
+        (cos_a * cos_b + sin_a * sin_b, sin_a * cos_b - cos_a * sin_b)
+    }
+}
+
+/// Light sampler that clusters lights into a bounding volume hierarchy and
+/// descends it stochastically, so lights close to the shading point and in
+/// front of it are chosen far more often than distant ones.
+pub struct BvhLightSampler {
+    lights: Vec<Light>,
+    nodes: Vec<LightNode>,
+    parents: Vec<Option<usize>>,
+    leaf_of_light: Vec<usize>,
+}
+
+impl BvhLightSampler {
+    pub fn new(lights: Vec<Light>) -> Self {
+        let mut sampler = Self {
+            nodes: Vec::with_capacity(2 * lights.len()),
+            parents: Vec::with_capacity(2 * lights.len()),
+            leaf_of_light: vec![0; lights.len()],
+            lights,
+        };
+
+        if !sampler.lights.is_empty() {
+            let mut indices: Vec<usize> = (0..sampler.lights.len()).collect();
+            sampler.build(&mut indices, None);
+        }
+
+        sampler
+    }
+
+    fn build(&mut self, indices: &mut [usize], parent: Option<usize>) -> usize {
+        let node_index = self.nodes.len();
+        self.parents.push(parent);
+
+        if indices.len() == 1 {
+            let light = indices[0];
+            self.leaf_of_light[light] = node_index;
+            self.nodes.push(LightNode {
+                bounds: self.lights[light].bounding_box(),
+                cone: self.lights[light].emission_cone(),
+                power: self.lights[light].power(),
+                kind: NodeKind::Leaf { light },
+            });
+            return node_index;
+        }
+
+        let centroid_bounds = indices.iter().fold(Aabb::empty(), |acc, &i| {
+            let c = self.lights[i].center();
+            Aabb::surrounding(&acc, &Aabb::from_points(c, c))
+        });
+        let axis = centroid_bounds.longest_axis();
+        indices.sort_by(|&a, &b| {
+            self.lights[a].center()[axis].total_cmp(&self.lights[b].center()[axis])
+        });
+
+        // Reserve the slot so children can refer to their parent.
+        self.nodes.push(LightNode {
+            bounds: Aabb::empty(),
+            cone: EmissionCone::everywhere(),
+            power: 0.0,
+            kind: NodeKind::Leaf { light: 0 },
+        });
+
+        let mid = indices.len() / 2;
+        let (left_indices, right_indices) = indices.split_at_mut(mid);
+        let left = self.build(left_indices, Some(node_index));
+        let right = self.build(right_indices, Some(node_index));
+
+        self.nodes[node_index] = LightNode {
+            bounds: Aabb::surrounding(&self.nodes[left].bounds, &self.nodes[right].bounds),
+            cone: EmissionCone::surrounding(&self.nodes[left].cone, &self.nodes[right].cone),
+            power: self.nodes[left].power + self.nodes[right].power,
+            kind: NodeKind::Interior { left, right },
+        };
+
+        node_index
+    }
+
+    /// Probability of descending into `left` rather than `right`.
+    fn left_probability(&self, left: usize, right: usize, p: Point3, n: Vec3) -> Option<f64> {
+        let left_importance = self.nodes[left].importance(p, n);
+        let right_importance = self.nodes[right].importance(p, n);
+        let total = left_importance + right_importance;
+        if total <= 0.0 {
+            None
+        } else {
+            Some(left_importance / total)
+        }
+    }
+
+    fn pdf_node(&self, node: usize, prob: f64, p: Point3, n: Vec3, ray: &Ray) -> f64 {
+        match self.nodes[node].kind {
+            NodeKind::Leaf { light } => prob * self.lights[light].pdf(p, &ray.direction()),
+            NodeKind::Interior { left, right } => {
+                let p_left = match self.left_probability(left, right, p, n) {
+                    Some(p_left) => p_left,
+                    None => return 0.0,
+                };
+
+                let mut pdf = 0.0;
+                if p_left > 0.0 && self.nodes[left].bounds.hit(ray, 0.0, f64::INFINITY) {
+                    pdf += self.pdf_node(left, prob * p_left, p, n, ray);
+                }
+                if p_left < 1.0 && self.nodes[right].bounds.hit(ray, 0.0, f64::INFINITY) {
+                    pdf += self.pdf_node(right, prob * (1.0 - p_left), p, n, ray);
+                }
+                pdf
+            }
+        }
+    }
+}
+
+impl LightSampler for BvhLightSampler {
+    fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    fn pick(&self, p: Point3, n: Vec3) -> Option<(usize, f64)> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut node = 0;
+        let mut pmf = 1.0;
+        loop {
+            match self.nodes[node].kind {
+                NodeKind::Leaf { light } => return Some((light, pmf)),
+                NodeKind::Interior { left, right } => {
+                    let p_left = self.left_probability(left, right, p, n)?;
+                    if random_double() < p_left {
+                        node = left;
+                        pmf *= p_left;
+                    } else {
+                        node = right;
+                        pmf *= 1.0 - p_left;
+                    }
+                }
+            }
+        }
+    }
+
+    fn pmf(&self, p: Point3, n: Vec3, index: usize) -> f64 {
+        let mut node = self.leaf_of_light[index];
+        let mut pmf = 1.0;
+        while let Some(parent) = self.parents[node] {
+            if let NodeKind::Interior { left, right } = self.nodes[parent].kind {
+                let p_left = match self.left_probability(left, right, p, n) {
+                    Some(p_left) => p_left,
+                    None => return 0.0,
+                };
+                pmf *= if node == left { p_left } else { 1.0 - p_left };
+            }
+            node = parent;
+        }
+        pmf
+    }
+
+    fn pdf(&self, p: Point3, n: Vec3, direction: &Vec3) -> f64 {
+        if self.nodes.is_empty() {
+            return 0.0;
+        }
+        let ray = Ray::new(p, *direction);
+        self.pdf_node(0, 1.0, p, n, &ray)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::{QuadLight, SphereLight};
+    use crate::vec3::Color;
+
+    fn grid() -> Vec<Light> {
+        let mut lights = Vec::new();
+        for a in -3..3 {
+            for b in -3..3 {
+                let center = Point3::new(a as f64 * 2.0, 0.2, b as f64 * 2.0);
+                lights.push(SphereLight::new(center, 0.2, Color::new(1.0, 1.0, 1.0)).into());
+            }
+        }
+        // A single-sided panel facing down, above the far corner.
+        lights.push(
+            QuadLight::new(
+                Point3::new(4.0, 3.0, 4.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                Color::new(1.0, 1.0, 1.0),
+                Color::zero(),
+            )
+            .into(),
+        );
+        lights
+    }
+
+    #[test]
+    fn test_pmf_sums_to_one() {
+        let sampler = BvhLightSampler::new(grid());
+        let p = Point3::new(0.5, -1.0, 0.5);
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        let total: f64 = (0..sampler.lights().len())
+            .map(|i| sampler.pmf(p, n, i))
+            .sum();
+        assert!((total - 1.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_pick_matches_pmf() {
+        let sampler = BvhLightSampler::new(grid());
+        let p = Point3::new(0.5, -1.0, 0.5);
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        for _ in 0..100 {
+            let (index, pmf) = sampler.pick(p, n).unwrap();
+            assert!((pmf - sampler.pmf(p, n, index)).abs() < 1e-10);
+        }
+    }
+
+    #[test]
+    fn test_nearby_lights_are_preferred() {
+        let sampler = BvhLightSampler::new(grid());
+        let p = Point3::new(-6.0, -1.0, -6.0);
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        let near = sampler.pmf(p, n, 0);
+        let far = sampler.pmf(p, n, sampler.lights().len() - 1);
+        assert!(near > far);
+    }
+
+    #[test]
+    fn test_pdf_matches_default_sum() {
+        let sampler = BvhLightSampler::new(grid());
+        let p = Point3::new(0.5, -1.0, 0.5);
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        let dir = sampler.lights()[14].center() - p;
+        let expected: f64 = sampler
+            .lights()
+            .iter()
+            .enumerate()
+            .map(|(i, l)| sampler.pmf(p, n, i) * l.pdf(p, &dir))
+            .sum();
+        assert!(expected > 0.0);
+        assert!((sampler.pdf(p, n, &dir) - expected).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_lights_behind_get_nothing() {
+        let sampler = BvhLightSampler::new(grid());
+        let quad = sampler.lights().len() - 1;
+
+        // Facing away from the spheres, towards the quad's dark side.
+        let p = Point3::new(4.5, 5.0, 4.5);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        assert!(sampler.pick(p, up).is_none());
+
+        // Facing the spheres and the quad's dark side.
+        let total: f64 = (0..quad).map(|i| sampler.pmf(p, -up, i)).sum();
+        assert!((total - 1.0).abs() < 1e-10);
+        assert_eq!(sampler.pmf(p, -up, quad), 0.0);
+    }
+
+    #[test]
+    fn test_quad_pdf_is_zero_from_behind() {
+        let sampler = BvhLightSampler::new(grid());
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        let quad = sampler.lights().len() - 1;
+
+        let below = Point3::new(0.5, 1.0, 0.5);
+        let dir = sampler.lights()[quad].center() - below;
+        let expected: f64 = sampler
+            .lights()
+            .iter()
+            .enumerate()
+            .map(|(i, l)| sampler.pmf(below, n, i) * l.pdf(below, &dir))
+            .sum();
+        assert!(expected > 0.0);
+        assert!((sampler.pdf(below, n, &dir) - expected).abs() < 1e-10);
+
+        let above = Point3::new(4.5, 5.0, 4.5);
+        let dir = sampler.lights()[quad].center() - above;
+        assert_eq!(sampler.pdf(above, -n, &dir), 0.0);
+    }
+}
@@ -1,22 +1,105 @@
-use raytracer::bababoi::{random_double, random_double_range};
-use raytracer::camera::Camera;
+use raytracer::bababoi::{self, random_double, random_double_range};
+use raytracer::camera::{Camera, ViewLayout};
+use raytracer::checkpoint::{Checkpoint, Checkpointing};
 use raytracer::hittable_list::HittableList;
-use raytracer::material::{Dielectric, Lambertian, Metal};
+use raytracer::material::{Dielectric, Dispersion, Lambertian, Metal};
+use raytracer::progressive::Progressive;
+use raytracer::sampler::IndependentSampler;
+use raytracer::sky::Sky;
 use raytracer::sphere::Sphere;
 use raytracer::vec3::{Color, Point3, Vec3};
 use std::env;
 use std::io;
+use std::time::Duration;
 
 fn main() -> io::Result<()> {
     // Parse command line arguments
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+
+    // A resumed render takes its settings from the checkpoint. Options given
+    // now come first, so their values win over the stored ones.
+    let mut resume = None;
+    if let Some(index) = args.iter().position(|a| a == "--resume") {
+        let path = match args.get(index + 1) {
+            Some(path) => path.clone(),
+            None => {
+                eprintln!("--resume needs a checkpoint file");
+                return Ok(());
+            }
+        };
+        let checkpoint = Checkpoint::load(&path)?;
+        args.drain(index..index + 2);
+        args.extend(checkpoint.options.iter().cloned());
+        resume = Some(checkpoint);
+    }
+
     let mut use_gpu = false;
+    let mut use_sky = false;
+    let mut spectral = false;
     let mut output_file = None;
+    let mut seed = None;
+    let mut time_limit = None;
+    let mut target_noise = None;
+    let mut checkpoint_file = None;
+    let mut stereo = None;
+    let mut layout = ViewLayout::SideBySide;
     
     // Simple argument parsing
     for arg in &args[1..] {
         match arg.as_str() {
             "--gpu" | "-g" => use_gpu = true,
+            "--sky" => use_sky = true,
+            "--spectral" => spectral = true,
+            "--seed" => {
+                let index = args.iter().position(|a| a == arg).unwrap();
+                seed = args.get(index + 1).and_then(|s| s.parse::<u64>().ok());
+                if seed.is_none() {
+                    eprintln!("--seed needs a whole number");
+                    return Ok(());
+                }
+            }
+            "--time" => {
+                let index = args.iter().position(|a| a == arg).unwrap();
+                time_limit = args
+                    .get(index + 1)
+                    .and_then(|s| s.parse::<f64>().ok())
+                    .filter(|t| t.is_finite() && *t >= 0.0);
+                if time_limit.is_none() {
+                    eprintln!("--time needs a non-negative number of seconds");
+                    return Ok(());
+                }
+            }
+            "--target-noise" => {
+                let index = args.iter().position(|a| a == arg).unwrap();
+                target_noise = args
+                    .get(index + 1)
+                    .and_then(|s| s.parse::<f64>().ok())
+                    .filter(|n| n.is_finite() && *n >= 0.0);
+                if target_noise.is_none() {
+                    eprintln!("--target-noise needs a non-negative number");
+                    return Ok(());
+                }
+            }
+            "--stereo" => {
+                let index = args.iter().position(|a| a == arg).unwrap();
+                stereo = args
+                    .get(index + 1)
+                    .and_then(|s| s.parse::<f64>().ok())
+                    .filter(|d| d.is_finite() && *d > 0.0);
+                if stereo.is_none() {
+                    eprintln!("--stereo needs a positive eye separation");
+                    return Ok(());
+                }
+            }
+            "--separate-views" => layout = ViewLayout::Separate,
+            "--checkpoint" => {
+                let index = args.iter().position(|a| a == arg).unwrap();
+                checkpoint_file = args.get(index + 1).cloned();
+                if checkpoint_file.is_none() {
+                    eprintln!("--checkpoint needs a file name");
+                    return Ok(());
+                }
+            }
             "-o" | "--output" => {
                 // Next argument is the output file
                 let index = args.iter().position(|a| a == arg).unwrap();
@@ -25,9 +108,17 @@ fn main() -> io::Result<()> {
                 }
             }
             _ => {
-                // Check if it's an output file after -o flag
-                if args.iter().position(|a| a == "-o" || a == "--output").unwrap_or(args.len()) 
-                   == args.iter().position(|a| a == arg).unwrap_or(args.len()) - 1 {
+                // Check if it's the value of an option
+                let index = args.iter().position(|a| a == arg).unwrap();
+                if matches!(
+                    args[index - 1].as_str(),
+                    "-o" | "--output"
+                        | "--seed"
+                        | "--time"
+                        | "--target-noise"
+                        | "--checkpoint"
+                        | "--stereo"
+                ) {
                     continue;
                 }
                 
@@ -46,7 +137,98 @@ fn main() -> io::Result<()> {
         eprintln!("Using CPU rendering");
     }
     
-    // Create the world
+    if (time_limit.is_some() || target_noise.is_some() || checkpoint_file.is_some())
+        && output_file.is_none()
+    {
+        eprintln!("--time, --target-noise and --checkpoint need an output file (-o)");
+        return Ok(());
+    }
+
+    if stereo.is_some() {
+        if output_file.is_none() {
+            eprintln!("--stereo needs an output file (-o)");
+            return Ok(());
+        }
+        if use_gpu
+            || time_limit.is_some()
+            || target_noise.is_some()
+            || checkpoint_file.is_some()
+            || resume.is_some()
+        {
+            eprintln!(
+                "--stereo can't be combined with --gpu, --time, --target-noise, --checkpoint or --resume"
+            );
+            return Ok(());
+        }
+    }
+
+    // A checkpointed render needs a seed to rebuild the same scene and
+    // continue the same samples when resumed.
+    let mut options: Vec<String> = args[1..].to_vec();
+    if checkpoint_file.is_some() && seed.is_none() {
+        let new_seed = (random_double() * (1u64 << 53) as f64) as u64;
+        options.extend(["--seed".to_string(), new_seed.to_string()]);
+        seed = Some(new_seed);
+    }
+    if let Some(checkpoint) = &resume {
+        options = checkpoint.options.clone();
+        seed = Some(checkpoint.seed);
+    }
+
+    // Create the world, the same one every time when seeded
+    let world = match seed {
+        Some(seed) => {
+            bababoi::with_sampler(Box::new(IndependentSampler::new(Some(seed))), || {
+                random_scene(spectral)
+            })
+        }
+        None => random_scene(spectral),
+    };
+
+    // Camera setup
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 1200;
+    cam.samples_per_pixel = 500;
+    cam.max_depth = 50;
+    cam.vfov = 20.0;
+    cam.lookfrom = Point3::new(13.0, 2.0, 3.0);
+    cam.lookat = Point3::new(0.0, 0.0, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+    cam.defocus_angle = 0.6;
+    cam.focus_dist = 10.0;
+    cam.use_gpu = use_gpu;
+    if use_sky {
+        cam.sky = Some(Sky::new(35.0, 60.0, 3.0));
+    }
+    cam.spectral = spectral;
+    cam.seed = seed;
+    if time_limit.is_some() || target_noise.is_some() {
+        cam.progressive = Some(Progressive {
+            time_limit: time_limit.map(Duration::from_secs_f64),
+            target_noise,
+            ..Progressive::default()
+        });
+    }
+    cam.checkpoint = checkpoint_file.map(|path| Checkpointing { path, options });
+
+    // Render the scene
+    if let (Some(eye_separation), Some(filename)) = (stereo, &output_file) {
+        // Converge the eyes on the focus plane.
+        let views = cam.stereo_views(eye_separation, cam.focus_dist);
+        cam.render_views(&world, &views, layout, filename)?;
+        return Ok(());
+    }
+    match (resume, output_file) {
+        (Some(checkpoint), _) => cam.resume_to_file(&world, checkpoint)?,
+        (None, Some(filename)) => cam.render_to_file(&world, &filename)?,
+        (None, None) => cam.render(&world)?,
+    }
+
+    Ok(())
+}
+
+fn random_scene(spectral: bool) -> HittableList {
     let mut world = HittableList::new();
 
     let ground_material = Lambertian::new(Color::new(0.5, 0.5, 0.5));
@@ -89,7 +271,12 @@ fn main() -> io::Result<()> {
     }
 
     // Big glass sphere in the center
-    let material1 = Dielectric::new(1.5);
+    // Dense flint glass splits light visibly when rendered spectrally.
+    let material1 = if spectral {
+        Dielectric::with_dispersion(Dispersion::sf11())
+    } else {
+        Dielectric::new(1.5)
+    };
     world.add(Box::new(Sphere::new(
         Point3::new(0.0, 1.0, 0.0),
         1.0,
@@ -112,25 +299,5 @@ fn main() -> io::Result<()> {
         material3,
     )));
 
-    // Camera setup
-    let mut cam = Camera::new();
-    cam.aspect_ratio = 16.0 / 9.0;
-    cam.image_width = 1200;
-    cam.samples_per_pixel = 500;
-    cam.max_depth = 50;
-    cam.vfov = 20.0;
-    cam.lookfrom = Point3::new(13.0, 2.0, 3.0);
-    cam.lookat = Point3::new(0.0, 0.0, 0.0);
-    cam.vup = Vec3::new(0.0, 1.0, 0.0);
-    cam.defocus_angle = 0.6;
-    cam.focus_dist = 10.0;
-    cam.use_gpu = use_gpu;
-
-    // Render the scene
-    match output_file {
-        Some(filename) => cam.render_to_file(&world, &filename)?,
-        None => cam.render(&world)?,
-    }
-
-    Ok(())
+    world
 }
@@ -1,23 +1,157 @@
+use crate::bababoi::random_double;
+use crate::fresnel::{self, FresnelModel};
 use crate::hittable::HitRecord;
+use crate::medium::Medium;
+use crate::microfacet::TrowbridgeReitz;
+use crate::onb::Onb;
 use crate::ray::Ray;
+use crate::texture::{SolidColor, Texture};
 use crate::vec3::{Color, Vec3};
+use std::f64::consts::PI;
 use std::sync::Arc;
 
 pub trait Material: Send + Sync {
+    /// Samples a scattered ray, returning it with the sample weight (the BSDF
+    /// times the cosine term, divided by the sampling density).
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
+
+    /// Like `scatter`, but also tells whether the direction came from a
+    /// delta lobe, whose density `pdf` doesn't include. Materials that mix
+    /// delta and non-delta lobes at the same point override this.
+    fn scatter_lobe(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray, bool)> {
+        let (attenuation, scattered) = self.scatter(r_in, rec)?;
+        Some((attenuation, scattered, self.is_delta()))
+    }
+
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
+        Color::zero()
+    }
+
+    /// BSDF times the cosine term for light leaving along `direction`. Delta
+    /// materials such as smooth glass return zero since they can't be sampled
+    /// from a light.
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> Color {
+        Color::zero()
+    }
+
+    /// Solid angle density with which `scatter` produces `direction`, or zero
+    /// for delta materials.
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    /// Whether the material only scatters into discrete directions, so that
+    /// `eval` and `pdf` are zero and light sampling is skipped.
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    /// Medium filling the inside of objects made of this material, entered
+    /// when a scattered ray crosses the surface.
+    fn interior_medium(&self) -> Option<Medium> {
+        None
+    }
+
+    /// Whether scattering depends on the ray's wavelength, so spectral paths
+    /// through this material are only valid for their hero wavelength.
+    fn is_dispersive(&self) -> bool {
+        false
+    }
 }
 
 pub struct Lambertian {
     albedo: Color,
 }
 
+/// Wavelength-dependent index of refraction, with wavelengths in nm.
+#[derive(Debug, Clone, Copy)]
+pub enum Dispersion {
+    /// Cauchy's equation `n = a + b / λ²`, with `b` in μm².
+    Cauchy { a: f64, b: f64 },
+    /// Sellmeier's equation with `c` coefficients in μm².
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    /// Borosilicate crown glass (Schott N-BK7).
+    pub fn bk7() -> Self {
+        Dispersion::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        }
+    }
+
+    /// Dense flint glass (Schott N-SF11), strongly dispersive.
+    pub fn sf11() -> Self {
+        Dispersion::Sellmeier {
+            b: [1.73759695, 0.313747346, 1.89878101],
+            c: [0.013188707, 0.0623068142, 155.23629],
+        }
+    }
+
+    pub fn refraction_index(&self, wavelength: f64) -> f64 {
+        let l = wavelength / 1000.0;
+        let l2 = l * l;
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: f64 = (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+}
+
+/// Wavelength at which dispersive glasses are evaluated in RGB rendering
+/// (the helium d line).
+const REFERENCE_WAVELENGTH: f64 = 587.6;
+
 pub struct Dielectric {
     refraction_index: f64,
+    interior: Option<Medium>,
+    dispersion: Option<Dispersion>,
 }
 
 impl Dielectric {
     pub fn new(refraction_index: f64) -> Arc<Self> {
-        Arc::new(Self { refraction_index })
+        Arc::new(Self {
+            refraction_index,
+            interior: None,
+            dispersion: None,
+        })
+    }
+
+    /// Glass whose index of refraction varies with wavelength. Spectral
+    /// renders split light into its colors; RGB renders use the index at
+    /// 587.6 nm.
+    pub fn with_dispersion(dispersion: Dispersion) -> Arc<Self> {
+        Arc::new(Self {
+            refraction_index: dispersion.refraction_index(REFERENCE_WAVELENGTH),
+            interior: None,
+            dispersion: Some(dispersion),
+        })
+    }
+
+    fn refraction_index_for(&self, ray: &Ray) -> f64 {
+        match (self.dispersion, ray.wavelength()) {
+            (Some(dispersion), Some(wavelength)) => dispersion.refraction_index(wavelength),
+            _ => self.refraction_index,
+        }
+    }
+
+    /// Tinted glass whose interior absorbs light following Beer-Lambert's
+    /// law, with `absorption` given per unit distance for each channel.
+    pub fn with_absorption(refraction_index: f64, absorption: Color) -> Arc<Self> {
+        Self::with_interior(refraction_index, Medium::absorbing(absorption))
+    }
+
+    /// Smooth boundary of an object filled with `medium`.
+    pub fn with_interior(refraction_index: f64, medium: Medium) -> Arc<Self> {
+        Arc::new(Self {
+            refraction_index,
+            interior: Some(medium),
+            dispersion: None,
+        })
     }
 }
 
@@ -26,11 +160,6 @@ pub struct Metal {
     fuzz: f64,
 }
 
-fn schlick(cosine: f64, ref_idx: f64) -> f64 {
-    let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
-    r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
-}
-
 impl Metal {
     pub fn new(albedo: Color, fuzz: f64) -> Arc<Self> {
         Arc::new(Self {
@@ -44,10 +173,11 @@ impl Material for Dielectric {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
         let attenuation = Color::new(1.0, 1.0, 1.0);
 
+        let refraction_index = self.refraction_index_for(r_in);
         let refraction_ratio = if rec.front_face {
-            1.0 / self.refraction_index
+            1.0 / refraction_index
         } else {
-            self.refraction_index
+            refraction_index
         };
 
         let unit_direction = r_in.direction().unit_vector();
@@ -56,9 +186,9 @@ impl Material for Dielectric {
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
 
-        let reflectance = schlick(cos_theta, refraction_ratio);
+        let reflectance = fresnel::schlick(cos_theta, refraction_ratio);
 
-        let direction = if cannot_refract || reflectance > crate::bababoi::random_double() {
+        let direction = if cannot_refract || reflectance > random_double() {
             unit_direction.reflect(&rec.normal)
         } else {
             unit_direction.refract(&rec.normal, refraction_ratio)
@@ -67,6 +197,257 @@ impl Material for Dielectric {
         let scattered = Ray::new(rec.p, direction);
         Some((attenuation, scattered))
     }
+
+    fn interior_medium(&self) -> Option<Medium> {
+        self.interior
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.dispersion.is_some()
+    }
+}
+
+/// Translucent material such as skin, wax or marble. Light refracts through a
+/// smooth dielectric boundary and random-walks through a scattering medium
+/// inside before leaving again, possibly far from where it entered.
+pub struct Subsurface {
+    boundary: Arc<Dielectric>,
+}
+
+impl Subsurface {
+    /// `albedo` is the overall color and `mean_free_path` how far light
+    /// travels inside before scattering, per channel and in scene units.
+    pub fn new(albedo: Color, mean_free_path: Color, refraction_index: f64) -> Arc<Self> {
+        Self::with_medium(
+            Medium::from_albedo(albedo, mean_free_path, 0.0),
+            refraction_index,
+        )
+    }
+
+    pub fn with_medium(medium: Medium, refraction_index: f64) -> Arc<Self> {
+        Arc::new(Self {
+            boundary: Dielectric::with_interior(refraction_index, medium),
+        })
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        self.boundary.scatter(r_in, rec)
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn interior_medium(&self) -> Option<Medium> {
+        self.boundary.interior_medium()
+    }
+}
+
+/// Glass with a rough surface, such as frosted glass, using GGX microfacet
+/// reflection and transmission (Walter et al. 2007).
+pub struct RoughDielectric {
+    refraction_index: f64,
+    distribution: TrowbridgeReitz,
+    fresnel: FresnelModel,
+    interior: Option<Medium>,
+}
+
+impl RoughDielectric {
+    pub fn new(refraction_index: f64, roughness: f64) -> Arc<Self> {
+        Self::with_fresnel(refraction_index, roughness, FresnelModel::Schlick)
+    }
+
+    pub fn with_fresnel(refraction_index: f64, roughness: f64, fresnel: FresnelModel) -> Arc<Self> {
+        Arc::new(Self {
+            refraction_index,
+            distribution: TrowbridgeReitz::from_roughness(roughness),
+            fresnel,
+            interior: None,
+        })
+    }
+
+    /// Frosted tinted glass; see `Dielectric::with_absorption`.
+    pub fn with_absorption(
+        refraction_index: f64,
+        roughness: f64,
+        fresnel: FresnelModel,
+        absorption: Color,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            refraction_index,
+            distribution: TrowbridgeReitz::from_roughness(roughness),
+            fresnel,
+            interior: Some(Medium::absorbing(absorption)),
+        })
+    }
+
+    /// Index of refraction across the surface as seen from the side the ray
+    /// arrives on.
+    fn relative_eta(&self, rec: &HitRecord) -> f64 {
+        if rec.front_face {
+            self.refraction_index
+        } else {
+            1.0 / self.refraction_index
+        }
+    }
+
+    /// Generalized half vector for a pair of local directions, facing +z.
+    fn half_vector(wo: &Vec3, wi: &Vec3, eta: f64) -> Option<Vec3> {
+        let etap = if wo.z() * wi.z() > 0.0 { 1.0 } else { eta };
+        let wm = *wi * etap + *wo;
+        if wi.z() == 0.0 || wo.z() == 0.0 || wm.near_zero() {
+            return None;
+        }
+        let wm = wm.unit_vector();
+        let wm = if wm.z() < 0.0 { -wm } else { wm };
+
+        // Discard back-facing microfacets.
+        if wm.dot(wi) * wi.z() < 0.0 || wm.dot(wo) * wo.z() < 0.0 {
+            return None;
+        }
+        Some(wm)
+    }
+
+    fn eval_local(&self, wo: &Vec3, wi: &Vec3, eta: f64) -> Color {
+        let wm = match Self::half_vector(wo, wi, eta) {
+            Some(wm) => wm,
+            None => return Color::zero(),
+        };
+
+        let cos_o = wo.z();
+        let cos_i = wi.z();
+        let f = self.fresnel.dielectric(wo.dot(&wm), eta);
+        let d = self.distribution.d(&wm);
+        let g = self.distribution.g(wo, wi);
+
+        let value = if cos_o * cos_i > 0.0 {
+            d * g * f / (4.0 * cos_o.abs())
+        } else {
+            let denom = (wi.dot(&wm) + wo.dot(&wm) / eta).powi(2) * cos_o;
+            // Radiance is compressed by eta^2 when it enters a denser medium.
+            d * (1.0 - f) * g * (wi.dot(&wm) * wo.dot(&wm) / denom).abs() / (eta * eta)
+        };
+
+        let v = value.abs();
+        Color::new(v, v, v)
+    }
+
+    fn pdf_local(&self, wo: &Vec3, wi: &Vec3, eta: f64) -> f64 {
+        let wm = match Self::half_vector(wo, wi, eta) {
+            Some(wm) => wm,
+            None => return 0.0,
+        };
+
+        let r = self.fresnel.dielectric(wo.dot(&wm), eta);
+        let t = 1.0 - r;
+        if r + t <= 0.0 {
+            return 0.0;
+        }
+
+        if wo.z() * wi.z() > 0.0 {
+            self.distribution.pdf(wo, &wm) / (4.0 * wo.dot(&wm).abs()) * r / (r + t)
+        } else {
+            let denom = (wi.dot(&wm) + wo.dot(&wm) / eta).powi(2);
+            let dwm_dwi = wi.dot(&wm).abs() / denom;
+            self.distribution.pdf(wo, &wm) * dwm_dwi * t / (r + t)
+        }
+    }
+}
+
+/// Refracts local direction `wo` about normal `n` on the same side, where
+/// `eta` is the relative index of refraction. Returns `None` on total
+/// internal reflection.
+fn refract_local(wo: &Vec3, n: &Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = wo.dot(n);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-*wo / eta + *n * (cos_i / eta - cos_t))
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let eta = self.relative_eta(rec);
+        let frame = Onb::new(&rec.normal);
+        let wo = frame.to_local(&-r_in.direction().unit_vector());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        if self.distribution.effectively_smooth() {
+            let n = Vec3::new(0.0, 0.0, 1.0);
+            let r = self.fresnel.dielectric(wo.z(), eta);
+            let (weight, wi) = match refract_local(&wo, &n, eta) {
+                Some(wt) if random_double() >= r => (1.0 / (eta * eta), wt),
+                _ => (1.0, Vec3::new(-wo.x(), -wo.y(), wo.z())),
+            };
+            let attenuation = Color::new(weight, weight, weight);
+            return Some((attenuation, Ray::new(rec.p, frame.transform(&wi))));
+        }
+
+        let wm = self
+            .distribution
+            .sample_wm(&wo, random_double(), random_double());
+        let r = self.fresnel.dielectric(wo.dot(&wm), eta);
+
+        let wi = if random_double() < r {
+            let wi = (-wo).reflect(&wm);
+            if wi.z() <= 0.0 {
+                return None;
+            }
+            wi
+        } else {
+            let wi = refract_local(&wo, &wm, eta)?;
+            if wi.z() >= 0.0 {
+                return None;
+            }
+            wi
+        };
+
+        let pdf = self.pdf_local(&wo, &wi, eta);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        let weight = self.eval_local(&wo, &wi, eta) / pdf;
+        Some((weight, Ray::new(rec.p, frame.transform(&wi))))
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        if self.distribution.effectively_smooth() {
+            return Color::zero();
+        }
+        let frame = Onb::new(&rec.normal);
+        let wo = frame.to_local(&-r_in.direction().unit_vector());
+        let wi = frame.to_local(&direction.unit_vector());
+        self.eval_local(&wo, &wi, self.relative_eta(rec))
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        if self.distribution.effectively_smooth() {
+            return 0.0;
+        }
+        let frame = Onb::new(&rec.normal);
+        let wo = frame.to_local(&-r_in.direction().unit_vector());
+        let wi = frame.to_local(&direction.unit_vector());
+        self.pdf_local(&wo, &wi, self.relative_eta(rec))
+    }
+
+    fn is_delta(&self) -> bool {
+        self.distribution.effectively_smooth()
+    }
+
+    fn interior_medium(&self) -> Option<Medium> {
+        self.interior
+    }
 }
 
 impl Material for Metal {
@@ -83,6 +464,145 @@ impl Material for Metal {
             None
         }
     }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+/// Rough metal with a GGX microfacet distribution and a complex index of
+/// refraction given per RGB channel.
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    distribution: TrowbridgeReitz,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f64) -> Arc<Self> {
+        Arc::new(Self {
+            eta,
+            k,
+            distribution: TrowbridgeReitz::from_roughness(roughness),
+        })
+    }
+
+    pub fn gold(roughness: f64) -> Arc<Self> {
+        Self::new(
+            Color::new(0.143, 0.374, 1.442),
+            Color::new(3.983, 2.385, 1.603),
+            roughness,
+        )
+    }
+
+    pub fn copper(roughness: f64) -> Arc<Self> {
+        Self::new(
+            Color::new(0.200, 0.924, 1.102),
+            Color::new(3.912, 2.452, 2.142),
+            roughness,
+        )
+    }
+
+    pub fn aluminium(roughness: f64) -> Arc<Self> {
+        Self::new(
+            Color::new(1.657, 0.880, 0.521),
+            Color::new(9.224, 6.270, 4.837),
+            roughness,
+        )
+    }
+
+    pub fn silver(roughness: f64) -> Arc<Self> {
+        Self::new(
+            Color::new(0.155, 0.117, 0.138),
+            Color::new(4.828, 3.122, 2.147),
+            roughness,
+        )
+    }
+
+    /// BSDF times cosine for local directions `wo` and `wi`.
+    fn eval_local(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::zero();
+        }
+        let wm = *wo + *wi;
+        if wm.near_zero() {
+            return Color::zero();
+        }
+        let wm = wm.unit_vector();
+
+        let f = fresnel::conductor(wo.dot(&wm).abs(), self.eta, self.k);
+        let d = self.distribution.d(&wm);
+        let g = self.distribution.g(wo, wi);
+        f * (d * g / (4.0 * wo.z()))
+    }
+
+    fn pdf_local(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let wm = *wo + *wi;
+        if wm.near_zero() {
+            return 0.0;
+        }
+        let wm = wm.unit_vector();
+        self.distribution.pdf(wo, &wm) / (4.0 * wo.dot(&wm).abs())
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let frame = Onb::new(&rec.normal);
+        let wo = frame.to_local(&-r_in.direction().unit_vector());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        if self.distribution.effectively_smooth() {
+            let wi = Vec3::new(-wo.x(), -wo.y(), wo.z());
+            let f = fresnel::conductor(wo.z(), self.eta, self.k);
+            return Some((f, Ray::new(rec.p, frame.transform(&wi))));
+        }
+
+        let wm = self
+            .distribution
+            .sample_wm(&wo, random_double(), random_double());
+        let wi = (-wo).reflect(&wm);
+        if wi.z() <= 0.0 {
+            return None;
+        }
+
+        let pdf = self.pdf_local(&wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        let weight = self.eval_local(&wo, &wi) / pdf;
+        Some((weight, Ray::new(rec.p, frame.transform(&wi))))
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        if self.distribution.effectively_smooth() {
+            return Color::zero();
+        }
+        let frame = Onb::new(&rec.normal);
+        let wo = frame.to_local(&-r_in.direction().unit_vector());
+        let wi = frame.to_local(&direction.unit_vector());
+        self.eval_local(&wo, &wi)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        if self.distribution.effectively_smooth() {
+            return 0.0;
+        }
+        let frame = Onb::new(&rec.normal);
+        let wo = frame.to_local(&-r_in.direction().unit_vector());
+        let wi = frame.to_local(&direction.unit_vector());
+        self.pdf_local(&wo, &wi)
+    }
+
+    fn is_delta(&self) -> bool {
+        self.distribution.effectively_smooth()
+    }
 }
 
 impl Lambertian {
@@ -103,4 +623,238 @@ impl Material for Lambertian {
 
         Some((self.albedo, scattered))
     }
+
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let cosine = rec.normal.dot(&direction.unit_vector());
+        if cosine <= 0.0 {
+            return Color::zero();
+        }
+        self.albedo * (cosine / PI)
+    }
+
+    fn pdf(&self, _r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        (rec.normal.dot(&direction.unit_vector()) / PI).max(0.0)
+    }
+}
+
+/// Rough diffuse surface such as clay or cloth, using the Oren-Nayar model.
+/// Unlike `Lambertian` it looks flatter and brighter towards the viewer.
+pub struct OrenNayar {
+    albedo: Arc<dyn Texture>,
+    a: f64,
+    b: f64,
+}
+
+impl OrenNayar {
+    /// `sigma` is the standard deviation of the microfacet slopes in degrees;
+    /// zero reduces to `Lambertian`.
+    pub fn new(albedo: Color, sigma: f64) -> Arc<Self> {
+        Self::with_texture(SolidColor::new(albedo), sigma)
+    }
+
+    pub fn with_texture(albedo: Arc<dyn Texture>, sigma: f64) -> Arc<Self> {
+        let sigma2 = sigma.to_radians().powi(2);
+        Arc::new(Self {
+            albedo,
+            a: 1.0 - sigma2 / (2.0 * (sigma2 + 0.33)),
+            b: 0.45 * sigma2 / (sigma2 + 0.09),
+        })
+    }
+
+    /// The factor scaling the Lambertian response for local directions.
+    fn roughness_term(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        let sin_o = (1.0 - wo.z() * wo.z()).max(0.0).sqrt();
+        let sin_i = (1.0 - wi.z() * wi.z()).max(0.0).sqrt();
+        if sin_o < 1e-4 || sin_i < 1e-4 {
+            return self.a;
+        }
+
+        // Cosine of the azimuth between the two directions.
+        let cos_dphi = ((wo.x() * wi.x() + wo.y() * wi.y()) / (sin_o * sin_i)).max(0.0);
+        let (sin_alpha, tan_beta) = if wi.z().abs() > wo.z().abs() {
+            (sin_o, sin_i / wi.z().abs())
+        } else {
+            (sin_i, sin_o / wo.z().abs())
+        };
+        self.a + self.b * cos_dphi * sin_alpha * tan_beta
+    }
+}
+
+impl Material for OrenNayar {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let frame = Onb::new(&rec.normal);
+        let wo = frame.to_local(&-r_in.direction().unit_vector());
+        let wi = Vec3::random_cosine_direction();
+
+        let albedo = self.albedo.value(rec.u, rec.v, &rec.p);
+        let weight = albedo * self.roughness_term(&wo, &wi);
+        Some((weight, Ray::new(rec.p, frame.transform(&wi))))
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let frame = Onb::new(&rec.normal);
+        let wo = frame.to_local(&-r_in.direction().unit_vector());
+        let wi = frame.to_local(&direction.unit_vector());
+        if wi.z() <= 0.0 {
+            return Color::zero();
+        }
+        let albedo = self.albedo.value(rec.u, rec.v, &rec.p);
+        albedo * (self.roughness_term(&wo, &wi) * wi.z() / PI)
+    }
+
+    fn pdf(&self, _r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        (rec.normal.dot(&direction.unit_vector()) / PI).max(0.0)
+    }
+}
+
+pub struct DiffuseLight {
+    emit: Color,
+    two_sided: bool,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Arc<Self> {
+        Arc::new(Self {
+            emit,
+            two_sided: true,
+        })
+    }
+
+    /// Light that only emits from the front of its surface, such as the
+    /// outside of a sphere.
+    pub fn single_sided(emit: Color) -> Arc<Self> {
+        Arc::new(Self {
+            emit,
+            two_sided: false,
+        })
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<(Color, Ray)> {
+        None
+    }
+
+    fn emitted(&self, _r_in: &Ray, rec: &HitRecord) -> Color {
+        if self.two_sided || rec.front_face {
+            self.emit
+        } else {
+            Color::zero()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Point3;
+
+    fn hit_from_above(mat: Arc<dyn Material>, r_in: &Ray) -> HitRecord {
+        let mut rec = HitRecord::new(Point3::zero(), Vec3::zero(), mat, 1.0);
+        rec.set_face_normal(r_in, Vec3::new(0.0, 1.0, 0.0));
+        rec
+    }
+
+    #[test]
+    fn test_conductor_conserves_energy() {
+        let mat = Conductor::silver(0.6);
+        let r_in = Ray::new(Point3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
+        let rec = hit_from_above(mat.clone(), &r_in);
+
+        let n = 20000;
+        let mut total = Color::zero();
+        for _ in 0..n {
+            if let Some((weight, _)) = mat.scatter(&r_in, &rec) {
+                total += weight;
+            }
+        }
+        let average = total / n as f64;
+        assert!(average.x() <= 1.0 && average.y() <= 1.0 && average.z() <= 1.0);
+        assert!(average.y() > 0.7);
+    }
+
+    #[test]
+    fn test_dispersion_decreases_with_wavelength() {
+        for dispersion in [Dispersion::bk7(), Dispersion::sf11(), Dispersion::Cauchy { a: 1.5046, b: 0.0042 }] {
+            assert!(dispersion.refraction_index(450.0) > dispersion.refraction_index(650.0));
+        }
+        assert!((Dispersion::bk7().refraction_index(587.6) - 1.5168).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_rough_dielectric_weight_matches_eval_over_pdf() {
+        for fresnel in [FresnelModel::Schlick, FresnelModel::Exact] {
+            let mat = RoughDielectric::with_fresnel(1.5, 0.5, fresnel);
+            let r_in = Ray::new(Point3::new(-1.0, 2.0, 0.5), Vec3::new(1.0, -2.0, -0.5));
+            let rec = hit_from_above(mat.clone(), &r_in);
+
+            let mut transmitted = 0;
+            for _ in 0..200 {
+                if let Some((weight, scattered)) = mat.scatter(&r_in, &rec) {
+                    if scattered.direction().y() < 0.0 {
+                        transmitted += 1;
+                    }
+                    let pdf = mat.pdf(&r_in, &rec, &scattered.direction());
+                    let expected = mat.eval(&r_in, &rec, &scattered.direction()) / pdf;
+                    assert!((weight - expected).length() < 1e-6);
+                }
+            }
+            assert!(transmitted > 100);
+        }
+    }
+
+    #[test]
+    fn test_single_sided_light() {
+        let outside = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let inside = Ray::new(Point3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let emit = Color::new(4.0, 4.0, 4.0);
+
+        let light = DiffuseLight::single_sided(emit);
+        assert_eq!(light.emitted(&outside, &hit_from_above(light.clone(), &outside)).x(), 4.0);
+        assert!(light.emitted(&inside, &hit_from_above(light.clone(), &inside)).near_zero());
+
+        let light = DiffuseLight::new(emit);
+        assert_eq!(light.emitted(&inside, &hit_from_above(light.clone(), &inside)).x(), 4.0);
+    }
+
+    #[test]
+    fn test_oren_nayar() {
+        let r_in = Ray::new(Point3::new(-1.0, 2.0, 0.5), Vec3::new(1.0, -2.0, -0.5));
+        let smooth = OrenNayar::new(Color::new(0.5, 0.5, 0.5), 0.0);
+        let lambertian = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        let rec = hit_from_above(smooth.clone(), &r_in);
+        let direction = Vec3::new(0.3, 1.0, -0.2);
+        let expected = lambertian.eval(&r_in, &rec, &direction);
+        assert!((smooth.eval(&r_in, &rec, &direction) - expected).length() < 1e-10);
+
+        let rough = OrenNayar::new(Color::new(1.0, 1.0, 1.0), 30.0);
+        let rec = hit_from_above(rough.clone(), &r_in);
+        let n = 20000;
+        let mut total = Color::zero();
+        for _ in 0..n {
+            if let Some((weight, scattered)) = rough.scatter(&r_in, &rec) {
+                let pdf = rough.pdf(&r_in, &rec, &scattered.direction());
+                let expected = rough.eval(&r_in, &rec, &scattered.direction()) / pdf;
+                assert!((weight - expected).length() < 1e-6);
+                total += weight;
+            }
+        }
+        let average = total / n as f64;
+        assert!(average.y() <= 1.0 && average.y() > 0.8);
+    }
+
+    #[test]
+    fn test_conductor_weight_matches_eval_over_pdf() {
+        let mat = Conductor::gold(0.4);
+        let r_in = Ray::new(Point3::new(-1.0, 2.0, 0.5), Vec3::new(1.0, -2.0, -0.5));
+        let rec = hit_from_above(mat.clone(), &r_in);
+
+        for _ in 0..100 {
+            if let Some((weight, scattered)) = mat.scatter(&r_in, &rec) {
+                let pdf = mat.pdf(&r_in, &rec, &scattered.direction());
+                let expected = mat.eval(&r_in, &rec, &scattered.direction()) / pdf;
+                assert!((weight - expected).length() < 1e-6);
+            }
+        }
+    }
 }
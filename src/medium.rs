@@ -0,0 +1,218 @@
+use crate::onb::Onb;
+use crate::vec3::{Color, Vec3};
+use std::f64::consts::PI;
+
+/// Homogeneous participating medium filling the inside of a closed object.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Medium {
+    /// Absorption coefficient per unit distance, for each RGB channel.
+    pub sigma_a: Color,
+    /// Scattering coefficient per unit distance, for each RGB channel.
+    pub sigma_s: Color,
+    /// Henyey-Greenstein asymmetry, from -1 (backward) to 1 (forward).
+    pub g: f64,
+}
+
+/// Outcome of following a ray through a scattering medium.
+#[derive(Debug, Clone, Copy)]
+pub enum MediumEvent {
+    /// The ray scatters after `distance`; its throughput is scaled by `weight`.
+    Scatter { distance: f64, weight: Color },
+    /// The ray reaches the end of the segment with its throughput scaled by
+    /// `weight`.
+    Pass { weight: Color },
+}
+
+impl Medium {
+    pub fn absorbing(sigma_a: Color) -> Self {
+        Self::new(sigma_a, Color::zero(), 0.0)
+    }
+
+    pub fn new(sigma_a: Color, sigma_s: Color, g: f64) -> Self {
+        Self {
+            sigma_a,
+            sigma_s,
+            g: g.clamp(-0.99, 0.99),
+        }
+    }
+
+    /// Scattering medium with the given multiple-scattering `albedo` and mean
+    /// free path per channel. The albedo is what a thick slab of the medium
+    /// looks like, which is easier to pick than single-scattering albedo
+    /// (inverted with the fit of Chiang et al. 2016).
+    pub fn from_albedo(albedo: Color, mean_free_path: Color, g: f64) -> Self {
+        let single = |a: f64| {
+            let a = a.clamp(0.0, 1.0);
+            1.0 - (4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt())
+                .powi(2)
+        };
+        let sigma_t = |mfp: f64| 1.0 / mfp.max(1e-6);
+        let sigma_s = Color::new(
+            single(albedo.x()) * sigma_t(mean_free_path.x()),
+            single(albedo.y()) * sigma_t(mean_free_path.y()),
+            single(albedo.z()) * sigma_t(mean_free_path.z()),
+        );
+        let sigma_a = Color::new(
+            sigma_t(mean_free_path.x()) - sigma_s.x(),
+            sigma_t(mean_free_path.y()) - sigma_s.y(),
+            sigma_t(mean_free_path.z()) - sigma_s.z(),
+        );
+        Self::new(sigma_a, sigma_s, g)
+    }
+
+    /// Absorbing medium that leaves `color` after light travels `distance`
+    /// through it, which is usually easier to art-direct than coefficients.
+    pub fn from_color_at_distance(color: Color, distance: f64) -> Self {
+        let coefficient = |c: f64| -c.clamp(1e-6, 1.0).ln() / distance;
+        Self::absorbing(Color::new(
+            coefficient(color.x()),
+            coefficient(color.y()),
+            coefficient(color.z()),
+        ))
+    }
+
+    /// Extinction coefficient, the sum of absorption and scattering.
+    pub fn sigma_t(&self) -> Color {
+        self.sigma_a + self.sigma_s
+    }
+
+    pub fn is_scattering(&self) -> bool {
+        !self.sigma_s.near_zero()
+    }
+
+    /// Beer-Lambert transmittance over `distance`.
+    pub fn transmittance(&self, distance: f64) -> Color {
+        let sigma_t = self.sigma_t();
+        Color::new(
+            channel_transmittance(sigma_t.x(), distance),
+            channel_transmittance(sigma_t.y(), distance),
+            channel_transmittance(sigma_t.z(), distance),
+        )
+    }
+
+    /// Samples where a ray travelling `t_max` through the medium first
+    /// scatters, if it does before then. The channel steering the distance is
+    /// picked from `u_channel`, and the weight accounts for all three.
+    pub fn sample_interaction(&self, t_max: f64, u_channel: f64, u_distance: f64) -> MediumEvent {
+        let sigma_t = self.sigma_t();
+        let channel = ((u_channel * 3.0) as usize).min(2);
+        let distance = if sigma_t[channel] > 0.0 {
+            -(1.0 - u_distance).ln() / sigma_t[channel]
+        } else {
+            f64::INFINITY
+        };
+
+        if distance < t_max {
+            let tr = self.transmittance(distance);
+            let density = sigma_t * tr;
+            let pdf = (density.x() + density.y() + density.z()) / 3.0;
+            MediumEvent::Scatter {
+                distance,
+                weight: self.sigma_s * tr / pdf,
+            }
+        } else {
+            let tr = self.transmittance(t_max);
+            let pdf = (tr.x() + tr.y() + tr.z()) / 3.0;
+            if pdf <= 0.0 {
+                // Transmittance underflowed in every channel, so nothing is
+                // left to carry.
+                return MediumEvent::Pass {
+                    weight: Color::zero(),
+                };
+            }
+            MediumEvent::Pass { weight: tr / pdf }
+        }
+    }
+
+    /// Samples a new direction for a ray travelling along unit `direction`
+    /// from the Henyey-Greenstein phase function. Its weight is one.
+    pub fn sample_phase(&self, direction: &Vec3, u1: f64, u2: f64) -> Vec3 {
+        let g = self.g;
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u1
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u1);
+            (1.0 + g * g - s * s) / (2.0 * g)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+
+        let local = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+        Onb::new(direction).transform(&local)
+    }
+}
+
+fn channel_transmittance(sigma_t: f64, distance: f64) -> f64 {
+    if sigma_t == 0.0 {
+        1.0
+    } else {
+        (-sigma_t * distance).exp()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transmittance() {
+        let medium = Medium::absorbing(Color::new(0.0, 1.0, 2.0));
+        let t = medium.transmittance(0.5);
+        assert_eq!(t.x(), 1.0);
+        assert!((t.y() - (-0.5f64).exp()).abs() < 1e-12);
+        assert!((t.z() - (-1.0f64).exp()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_color_at_distance_round_trips() {
+        let color = Color::new(0.9, 0.5, 0.2);
+        let medium = Medium::from_color_at_distance(color, 2.0);
+        let t = medium.transmittance(2.0);
+        assert!((t - color).length() < 1e-10);
+    }
+
+    #[test]
+    fn test_albedo_inversion_limits() {
+        let mfp = Color::new(1.0, 1.0, 1.0);
+        let white = Medium::from_albedo(Color::new(1.0, 1.0, 1.0), mfp, 0.0);
+        assert!(white.sigma_a.length() < 1e-4);
+        let black = Medium::from_albedo(Color::zero(), mfp, 0.0);
+        assert!(black.sigma_s.length() < 1e-4);
+    }
+
+    #[test]
+    fn test_gray_medium_scatter_weight_is_albedo() {
+        let medium = Medium::new(Color::new(0.5, 0.5, 0.5), Color::new(1.5, 1.5, 1.5), 0.0);
+        match medium.sample_interaction(f64::INFINITY, 0.4, 0.7) {
+            MediumEvent::Scatter { weight, .. } => {
+                assert!((weight - Color::new(0.75, 0.75, 0.75)).length() < 1e-10)
+            }
+            MediumEvent::Pass { .. } => panic!("infinite medium must scatter"),
+        }
+    }
+
+    #[test]
+    fn test_passing_a_thick_medium_carries_nothing() {
+        let medium = Medium::new(Color::new(1.0, 1.0, 1.0), Color::new(1.0, 1.0, 1.0), 0.0);
+        match medium.sample_interaction(1e4, 0.5, 1.0) {
+            MediumEvent::Pass { weight } => assert!(weight.x() == 0.0 && weight.near_zero()),
+            MediumEvent::Scatter { .. } => panic!("sampled distance is infinite"),
+        }
+    }
+
+    #[test]
+    fn test_phase_mean_cosine_is_g() {
+        let medium = Medium::new(Color::zero(), Color::new(1.0, 1.0, 1.0), 0.6);
+        let direction = Vec3::new(0.0, 0.0, 1.0);
+        let n = 100;
+        let mut total = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let u1 = (i as f64 + 0.5) / n as f64;
+                let u2 = (j as f64 + 0.5) / n as f64;
+                total += medium.sample_phase(&direction, u1, u2).dot(&direction);
+            }
+        }
+        assert!((total / (n * n) as f64 - 0.6).abs() < 1e-2);
+    }
+}
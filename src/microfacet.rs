@@ -0,0 +1,137 @@
+use crate::vec3::Vec3;
+use std::f64::consts::PI;
+
+/// Trowbridge-Reitz (GGX) microfacet distribution.
+///
+/// All directions are in the local shading frame, where the macro surface
+/// normal is +z.
+#[derive(Debug, Clone, Copy)]
+pub struct TrowbridgeReitz {
+    alpha: f64,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha: f64) -> Self {
+        Self {
+            alpha: alpha.max(1e-4),
+        }
+    }
+
+    /// Maps perceptual roughness in [0, 1] to the distribution's alpha.
+    pub fn from_roughness(roughness: f64) -> Self {
+        let r = roughness.clamp(0.0, 1.0);
+        Self::new(r * r)
+    }
+
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    /// Below this roughness the surface is treated as a perfect mirror.
+    pub fn effectively_smooth(&self) -> bool {
+        self.alpha < 1e-3
+    }
+
+    /// Density of microfacet normals `wm`.
+    pub fn d(&self, wm: &Vec3) -> f64 {
+        let cos2 = wm.z() * wm.z();
+        if cos2 <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        let denom = cos2 * (a2 - 1.0) + 1.0;
+        a2 / (PI * denom * denom)
+    }
+
+    pub fn lambda(&self, w: &Vec3) -> f64 {
+        let cos2 = w.z() * w.z();
+        if cos2 <= 0.0 {
+            return 0.0;
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0) / 2.0
+    }
+
+    /// Masking function for a single direction.
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Height-correlated masking-shadowing for a pair of directions.
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of normals visible from `w`.
+    pub fn visible_d(&self, w: &Vec3, wm: &Vec3) -> f64 {
+        let cos = w.z().abs();
+        if cos <= 0.0 {
+            return 0.0;
+        }
+        self.g1(w) / cos * self.d(wm) * w.dot(wm).abs()
+    }
+
+    /// Density with which `sample_wm` returns `wm`.
+    pub fn pdf(&self, w: &Vec3, wm: &Vec3) -> f64 {
+        self.visible_d(w, wm)
+    }
+
+    /// Samples a microfacet normal visible from `w` (Heitz 2018).
+    pub fn sample_wm(&self, w: &Vec3, u1: f64, u2: f64) -> Vec3 {
+        let mut wh = Vec3::new(self.alpha * w.x(), self.alpha * w.y(), w.z()).unit_vector();
+        if wh.z() < 0.0 {
+            wh = -wh;
+        }
+
+        let t1 = if wh.z() < 0.99999 {
+            Vec3::new(0.0, 0.0, 1.0).cross(&wh).unit_vector()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(&t1);
+
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let px = r * phi.cos();
+        let py = r * phi.sin();
+
+        // Warp the disk sample to the projected hemisphere of visible normals.
+        let h = (1.0 - px * px).max(0.0).sqrt();
+        let s = (1.0 + wh.z()) / 2.0;
+        let py = (1.0 - s) * h + s * py;
+        let pz = (1.0 - px * px - py * py).max(0.0).sqrt();
+
+        let nh = t1 * px + t2 * py + wh * pz;
+        Vec3::new(self.alpha * nh.x(), self.alpha * nh.y(), nh.z().max(1e-6)).unit_vector()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_d_is_normalized() {
+        // The projected area of the microfacets must equal the macro surface.
+        let distrib = TrowbridgeReitz::new(0.3);
+        let n = 400;
+        let mut total = 0.0;
+        for i in 0..n {
+            let theta = (i as f64 + 0.5) / n as f64 * PI / 2.0;
+            let wm = Vec3::new(theta.sin(), 0.0, theta.cos());
+            total += distrib.d(&wm) * theta.cos() * theta.sin() * 2.0 * PI * (PI / 2.0 / n as f64);
+        }
+        assert!((total - 1.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_sampled_normals_are_visible() {
+        let distrib = TrowbridgeReitz::new(0.5);
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        for i in 0..100 {
+            let wm = distrib.sample_wm(&wo, (i as f64 + 0.5) / 100.0, (i * 37 % 100) as f64 / 100.0);
+            assert!(wm.z() > 0.0);
+            assert!(distrib.pdf(&wo, &wm) >= 0.0);
+        }
+    }
+}
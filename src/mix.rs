@@ -0,0 +1,191 @@
+use crate::bababoi::hash_to_unit;
+use crate::hittable::HitRecord;
+use crate::material::Material;
+use crate::medium::Medium;
+use crate::ray::Ray;
+use crate::texture::{SolidColor, Texture};
+use crate::vec3::{Color, Vec3};
+use std::sync::Arc;
+
+/// Blends two materials by picking one of them at each hit point, with the
+/// chance of `second` given by a weight texture. The pick is a hash of the
+/// hit position, so every query at a hit agrees on the material and delta
+/// materials can be blended too.
+pub struct MixMaterial {
+    first: Arc<dyn Material>,
+    second: Arc<dyn Material>,
+    weight: Arc<dyn Texture>,
+}
+
+impl MixMaterial {
+    pub fn new(first: Arc<dyn Material>, second: Arc<dyn Material>, weight: f64) -> Arc<Self> {
+        Self::with_texture(first, second, SolidColor::gray(weight))
+    }
+
+    pub fn with_texture(
+        first: Arc<dyn Material>,
+        second: Arc<dyn Material>,
+        weight: Arc<dyn Texture>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            first,
+            second,
+            weight,
+        })
+    }
+
+    fn choose(&self, rec: &HitRecord) -> &Arc<dyn Material> {
+        let weight = self.weight.scalar(rec.u, rec.v, &rec.p);
+        if hash_to_unit(&[rec.p.x(), rec.p.y(), rec.p.z()]) < weight {
+            &self.second
+        } else {
+            &self.first
+        }
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        self.choose(rec).scatter(r_in, rec)
+    }
+
+    fn scatter_lobe(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray, bool)> {
+        self.choose(rec).scatter_lobe(r_in, rec)
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        self.choose(rec).emitted(r_in, rec)
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.choose(rec).eval(r_in, rec, direction)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        self.choose(rec).pdf(r_in, rec, direction)
+    }
+
+    fn is_delta(&self) -> bool {
+        self.first.is_delta() && self.second.is_delta()
+    }
+
+    /// Media are tracked per object rather than per hit, so the first
+    /// material's interior wins.
+    fn interior_medium(&self) -> Option<Medium> {
+        self.first
+            .interior_medium()
+            .or_else(|| self.second.interior_medium())
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.first.is_dispersive() || self.second.is_dispersive()
+    }
+}
+
+/// Uses one material for the front faces of a surface and another for its
+/// back faces, such as a leaf with a different underside.
+pub struct TwoSided {
+    front: Arc<dyn Material>,
+    back: Arc<dyn Material>,
+}
+
+impl TwoSided {
+    pub fn new(front: Arc<dyn Material>, back: Arc<dyn Material>) -> Arc<Self> {
+        Arc::new(Self { front, back })
+    }
+
+    fn choose(&self, rec: &HitRecord) -> &Arc<dyn Material> {
+        if rec.front_face {
+            &self.front
+        } else {
+            &self.back
+        }
+    }
+}
+
+impl Material for TwoSided {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        self.choose(rec).scatter(r_in, rec)
+    }
+
+    fn scatter_lobe(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray, bool)> {
+        self.choose(rec).scatter_lobe(r_in, rec)
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        self.choose(rec).emitted(r_in, rec)
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.choose(rec).eval(r_in, rec, direction)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        self.choose(rec).pdf(r_in, rec, direction)
+    }
+
+    fn is_delta(&self) -> bool {
+        self.front.is_delta() && self.back.is_delta()
+    }
+
+    fn interior_medium(&self) -> Option<Medium> {
+        self.front
+            .interior_medium()
+            .or_else(|| self.back.interior_medium())
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.front.is_dispersive() || self.back.is_dispersive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec3::Point3;
+
+    #[test]
+    fn test_mix_follows_weight() {
+        let red = Lambertian::new(Color::new(1.0, 0.0, 0.0));
+        let blue = Lambertian::new(Color::new(0.0, 0.0, 1.0));
+        let mix = MixMaterial::new(red, blue, 0.25);
+        let r_in = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let direction = Vec3::new(0.0, 1.0, 0.0);
+
+        let n = 4000;
+        let mut blue_hits = 0;
+        for i in 0..n {
+            let p = Point3::new(i as f64 * 0.01, 0.0, 0.0);
+            let mut rec = HitRecord::new(p, Vec3::zero(), mix.clone(), 1.0);
+            rec.set_face_normal(&r_in, Vec3::new(0.0, 1.0, 0.0));
+
+            let f = mix.eval(&r_in, &rec, &direction);
+            assert_eq!((f - mix.eval(&r_in, &rec, &direction)).length(), 0.0);
+            if f.z() > 0.0 {
+                blue_hits += 1;
+            }
+        }
+        let fraction = blue_hits as f64 / n as f64;
+        assert!((fraction - 0.25).abs() < 0.03);
+    }
+
+    #[test]
+    fn test_two_sided_picks_by_face() {
+        let red = Lambertian::new(Color::new(1.0, 0.0, 0.0));
+        let blue = Lambertian::new(Color::new(0.0, 0.0, 1.0));
+        let mat = TwoSided::new(red, blue);
+        let direction = Vec3::new(0.0, 1.0, 0.0);
+
+        for (from, front) in [(1.0, true), (-1.0, false)] {
+            let r_in = Ray::new(Point3::new(0.0, from, 0.0), Vec3::new(0.0, -from, 0.0));
+            let mut rec = HitRecord::new(Point3::zero(), Vec3::zero(), mat.clone(), 1.0);
+            rec.set_face_normal(&r_in, Vec3::new(0.0, 1.0, 0.0));
+            assert_eq!(rec.front_face, front);
+
+            let f = mat.eval(&r_in, &rec, &(direction * from));
+            assert_eq!(f.x() > 0.0, front);
+            assert_eq!(f.z() > 0.0, !front);
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use crate::vec3::Vec3;
+
+/// Orthonormal basis built around a single direction, used to move sampled
+/// directions from a local frame (with `w` as +z) into world space.
+#[derive(Debug, Clone, Copy)]
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    pub fn new(n: &Vec3) -> Self {
+        let w = n.unit_vector();
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(&a).unit_vector();
+        let u = w.cross(&v);
+
+        Self { u, v, w }
+    }
+
+    pub fn u(&self) -> Vec3 {
+        self.u
+    }
+
+    pub fn v(&self) -> Vec3 {
+        self.v
+    }
+
+    pub fn w(&self) -> Vec3 {
+        self.w
+    }
+
+    /// Transforms a vector from local basis coordinates to world space.
+    pub fn transform(&self, v: &Vec3) -> Vec3 {
+        self.u * v.x() + self.v * v.y() + self.w * v.z()
+    }
+
+    /// Transforms a world space vector into local basis coordinates.
+    pub fn to_local(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(v.dot(&self.u), v.dot(&self.v), v.dot(&self.w))
+    }
+}
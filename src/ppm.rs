@@ -0,0 +1,143 @@
+use crate::vec3::Color;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Image read from a PPM or PGM file, with channel values scaled to [0, 1]
+/// and stored row by row from the top left.
+pub struct PpmImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl PpmImage {
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Reads a plain or binary PPM (P3, P6) or PGM (P2, P5) file, as written by
+/// `Camera::render_to_file` and most image editors.
+pub fn read_ppm<P: AsRef<Path>>(path: P) -> io::Result<PpmImage> {
+    parse_ppm(&fs::read(path)?)
+}
+
+pub fn parse_ppm(data: &[u8]) -> io::Result<PpmImage> {
+    let mut pos = 0;
+    let mut header = Vec::new();
+    while header.len() < 4 {
+        // Skip whitespace and comments between header fields.
+        while pos < data.len() && (data[pos].is_ascii_whitespace() || data[pos] == b'#') {
+            if data[pos] == b'#' {
+                while pos < data.len() && data[pos] != b'\n' {
+                    pos += 1;
+                }
+            } else {
+                pos += 1;
+            }
+        }
+        let start = pos;
+        while pos < data.len() && !data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if start == pos {
+            return Err(invalid("truncated PPM header"));
+        }
+        header.push(String::from_utf8_lossy(&data[start..pos]).into_owned());
+    }
+
+    let channels = match header[0].as_str() {
+        "P2" | "P5" => 1,
+        "P3" | "P6" => 3,
+        _ => return Err(invalid("not a PPM or PGM file")),
+    };
+    let number = |s: &str| s.parse::<usize>().map_err(|_| invalid("bad PPM header"));
+    let width = number(&header[1])?;
+    let height = number(&header[2])?;
+    let max_value = number(&header[3])?;
+    if max_value == 0 || max_value > 65535 {
+        return Err(invalid("bad PPM maximum value"));
+    }
+
+    let count = width
+        .checked_mul(height)
+        .and_then(|n| n.checked_mul(channels))
+        .ok_or_else(|| invalid("PPM dimensions too large"))?;
+    let values: Vec<f64> = if header[0] == "P2" || header[0] == "P3" {
+        String::from_utf8_lossy(&data[pos..])
+            .split_ascii_whitespace()
+            .take(count)
+            .map(|s| s.parse::<usize>().map(|v| v as f64))
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid("bad PPM pixel value"))?
+    } else {
+        // A single whitespace byte separates the header from binary data.
+        let body = data.get(pos + 1..).unwrap_or(&[]);
+        if max_value < 256 {
+            body.iter().take(count).map(|&b| b as f64).collect()
+        } else {
+            body.chunks_exact(2)
+                .take(count)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]) as f64)
+                .collect()
+        }
+    };
+    if values.len() < count {
+        return Err(invalid("truncated PPM pixel data"));
+    }
+
+    let scale = 1.0 / max_value as f64;
+    let pixels = values
+        .chunks_exact(channels)
+        .map(|c| match channels {
+            1 => Color::new(c[0], c[0], c[0]) * scale,
+            _ => Color::new(c[0], c[1], c[2]) * scale,
+        })
+        .collect();
+
+    Ok(PpmImage {
+        width,
+        height,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_plain_ppm() {
+        let image = parse_ppm(b"P3\n# comment\n2 1\n255\n255 0 0  0 0 255\n").unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.pixel(0, 0).x(), 1.0);
+        assert_eq!(image.pixel(1, 0).z(), 1.0);
+    }
+
+    #[test]
+    fn test_parse_binary_pgm() {
+        let mut data = b"P5 2 2 255\n".to_vec();
+        data.extend_from_slice(&[0, 51, 102, 255]);
+        let image = parse_ppm(&data).unwrap();
+        assert!((image.pixel(1, 0).y() - 0.2).abs() < 1e-10);
+        assert_eq!(image.pixel(1, 1).x(), 1.0);
+    }
+
+    #[test]
+    fn test_rejects_truncated_data() {
+        assert!(parse_ppm(b"P3 2 2 255 1 2 3").is_err());
+        assert!(parse_ppm(b"GIF89a").is_err());
+    }
+
+    #[test]
+    fn test_rejects_overflowing_size() {
+        let header = format!("P6 {} {} 255 ", usize::MAX, 2);
+        let err = parse_ppm(header.as_bytes()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,516 @@
+use crate::bababoi::random_double;
+use crate::color::luminance;
+use crate::fresnel;
+use crate::hittable::HitRecord;
+use crate::material::Material;
+use crate::microfacet::TrowbridgeReitz;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::texture::{ChannelTexture, ScaledTexture, SolidColor, Texture};
+use crate::vec3::{Color, Vec3};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// Parameters of the principled BSDF. Every parameter except `ior` is a
+/// texture; scalar parameters read the texture's red channel and are expected
+/// to lie in [0, 1].
+#[derive(Clone)]
+pub struct PrincipledParams {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    /// Dielectric specular strength; 0.5 gives the common 4% reflectance.
+    pub specular: Arc<dyn Texture>,
+    /// Tints dielectric specular towards the base color.
+    pub specular_tint: Arc<dyn Texture>,
+    /// Extra grazing reflection for cloth.
+    pub sheen: Arc<dyn Texture>,
+    pub sheen_tint: Arc<dyn Texture>,
+    /// Strength of a second, fixed-IOR specular layer.
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_gloss: Arc<dyn Texture>,
+    /// Fraction of non-metallic light refracted through the surface.
+    pub transmission: Arc<dyn Texture>,
+    pub ior: f64,
+}
+
+impl Default for PrincipledParams {
+    fn default() -> Self {
+        Self {
+            base_color: SolidColor::gray(0.8),
+            metallic: SolidColor::gray(0.0),
+            roughness: SolidColor::gray(0.5),
+            specular: SolidColor::gray(0.5),
+            specular_tint: SolidColor::gray(0.0),
+            sheen: SolidColor::gray(0.0),
+            sheen_tint: SolidColor::gray(0.5),
+            clearcoat: SolidColor::gray(0.0),
+            clearcoat_gloss: SolidColor::gray(1.0),
+            transmission: SolidColor::gray(0.0),
+            ior: 1.5,
+        }
+    }
+}
+
+impl PrincipledParams {
+    /// Maps a glTF 2.0 metallic-roughness material. Following the glTF
+    /// convention, the metallic-roughness texture stores roughness in its
+    /// green channel and metalness in its blue channel, and each factor
+    /// scales its texture. Extensions aren't read here; set them on the
+    /// result, mapping `KHR_materials_transmission` to `transmission`,
+    /// `KHR_materials_ior` to `ior`, `KHR_materials_clearcoat` to `clearcoat`
+    /// (with `clearcoat_gloss` as one minus the clearcoat roughness) and
+    /// `KHR_materials_sheen` to `sheen`.
+    pub fn from_gltf(
+        base_color_factor: Color,
+        base_color_texture: Option<Arc<dyn Texture>>,
+        metallic_factor: f64,
+        roughness_factor: f64,
+        metallic_roughness_texture: Option<Arc<dyn Texture>>,
+    ) -> Self {
+        let base_color: Arc<dyn Texture> = match base_color_texture {
+            Some(tex) => ScaledTexture::new(tex, base_color_factor),
+            None => SolidColor::new(base_color_factor),
+        };
+
+        let (metallic, roughness): (Arc<dyn Texture>, Arc<dyn Texture>) =
+            match metallic_roughness_texture {
+                Some(tex) => (
+                    ChannelTexture::new(tex.clone(), 2, metallic_factor),
+                    ChannelTexture::new(tex, 1, roughness_factor),
+                ),
+                None => (
+                    SolidColor::gray(metallic_factor),
+                    SolidColor::gray(roughness_factor),
+                ),
+            };
+
+        Self {
+            base_color,
+            metallic,
+            roughness,
+            ..Self::default()
+        }
+    }
+}
+
+/// Disney-style principled BSDF combining diffuse, sheen, specular,
+/// clearcoat and transmission lobes. Lobes are chosen stochastically, and
+/// the full BSDF is evaluated for every sample so that light sampling and
+/// multiple importance sampling see one consistent distribution.
+pub struct Principled {
+    params: PrincipledParams,
+}
+
+/// Principled parameters evaluated at a single shading point.
+struct Lobes {
+    base: Color,
+    roughness: f64,
+    specular_f0: Color,
+    sheen_color: Color,
+    clearcoat: f64,
+    clearcoat_alpha: f64,
+    diffuse_weight: f64,
+    transmission_weight: f64,
+    distribution: TrowbridgeReitz,
+    eta: f64,
+}
+
+/// Probabilities of sampling each lobe.
+struct LobeProbabilities {
+    diffuse: f64,
+    specular: f64,
+    clearcoat: f64,
+    transmission: f64,
+}
+
+fn schlick_weight(cosine: f64) -> f64 {
+    (1.0 - cosine.clamp(0.0, 1.0)).powi(5)
+}
+
+fn lerp_color(a: Color, b: Color, t: f64) -> Color {
+    a * (1.0 - t) + b * t
+}
+
+/// Berry's GTR1 distribution, used by the clearcoat lobe.
+fn gtr1(cos_h: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    let t = 1.0 + (a2 - 1.0) * cos_h * cos_h;
+    (a2 - 1.0) / (PI * a2.ln() * t)
+}
+
+fn sample_gtr1(alpha: f64) -> Vec3 {
+    let a2 = alpha * alpha;
+    let cos_h = ((1.0 - a2.powf(1.0 - random_double())) / (1.0 - a2))
+        .max(0.0)
+        .sqrt();
+    let sin_h = (1.0 - cos_h * cos_h).max(0.0).sqrt();
+    let phi = 2.0 * PI * random_double();
+    Vec3::new(sin_h * phi.cos(), sin_h * phi.sin(), cos_h)
+}
+
+impl Principled {
+    pub fn new(params: PrincipledParams) -> Arc<Self> {
+        Arc::new(Self { params })
+    }
+
+    fn lobes(&self, rec: &HitRecord) -> Lobes {
+        let (u, v, p) = (rec.u, rec.v, &rec.p);
+        let scalar = |tex: &Arc<dyn Texture>| tex.scalar(u, v, p).clamp(0.0, 1.0);
+
+        let base = self.params.base_color.value(u, v, p);
+        let metallic = scalar(&self.params.metallic);
+        let roughness = scalar(&self.params.roughness);
+        let specular = scalar(&self.params.specular);
+        let specular_tint = scalar(&self.params.specular_tint);
+        let sheen = scalar(&self.params.sheen);
+        let sheen_tint = scalar(&self.params.sheen_tint);
+        let clearcoat = scalar(&self.params.clearcoat);
+        let clearcoat_gloss = scalar(&self.params.clearcoat_gloss);
+        let transmission = scalar(&self.params.transmission);
+
+        let white = Color::new(1.0, 1.0, 1.0);
+        let lum = luminance(base);
+        let tint = if lum > 0.0 { base / lum } else { white };
+
+        let dielectric_f0 = lerp_color(white, tint, specular_tint) * (specular * 0.08);
+        let eta = if rec.front_face {
+            self.params.ior
+        } else {
+            1.0 / self.params.ior
+        };
+
+        Lobes {
+            base,
+            roughness,
+            specular_f0: lerp_color(dielectric_f0, base, metallic),
+            sheen_color: lerp_color(white, tint, sheen_tint) * sheen,
+            clearcoat,
+            clearcoat_alpha: 0.1 * (1.0 - clearcoat_gloss) + 0.001 * clearcoat_gloss,
+            diffuse_weight: (1.0 - metallic) * (1.0 - transmission),
+            transmission_weight: (1.0 - metallic) * transmission,
+            distribution: TrowbridgeReitz::new((roughness * roughness).max(1e-3)),
+            eta,
+        }
+    }
+
+    fn probabilities(lobes: &Lobes, wo: &Vec3) -> Option<LobeProbabilities> {
+        let fresnel_weight = schlick_weight(wo.z());
+        let base_lum = luminance(lobes.base).max(0.01);
+
+        let diffuse = lobes.diffuse_weight * base_lum;
+        let specular = luminance(lerp_color(
+            lobes.specular_f0,
+            Color::new(1.0, 1.0, 1.0),
+            fresnel_weight,
+        ))
+        .max(0.02);
+        let clearcoat = 0.25 * lobes.clearcoat * (0.04 + 0.96 * fresnel_weight);
+        let transmission = lobes.transmission_weight * base_lum;
+
+        let total = diffuse + specular + clearcoat + transmission;
+        if total <= 0.0 {
+            return None;
+        }
+
+        Some(LobeProbabilities {
+            diffuse: diffuse / total,
+            specular: specular / total,
+            clearcoat: clearcoat / total,
+            transmission: transmission / total,
+        })
+    }
+
+    /// Microfacet normal for a refracted pair of directions, facing +z.
+    fn transmission_half_vector(wo: &Vec3, wi: &Vec3, eta: f64) -> Option<Vec3> {
+        let wm = *wi * eta + *wo;
+        if wm.near_zero() {
+            return None;
+        }
+        let wm = wm.unit_vector();
+        let wm = if wm.z() < 0.0 { -wm } else { wm };
+        if wm.dot(wi) * wi.z() < 0.0 || wm.dot(wo) * wo.z() < 0.0 {
+            return None;
+        }
+        Some(wm)
+    }
+
+    /// BSDF times cosine for local directions, with `wo` above the surface.
+    fn eval_local(lobes: &Lobes, wo: &Vec3, wi: &Vec3) -> Color {
+        let cos_o = wo.z();
+        let cos_i = wi.z();
+        if cos_o <= 0.0 || cos_i == 0.0 {
+            return Color::zero();
+        }
+
+        if cos_i < 0.0 {
+            if lobes.transmission_weight <= 0.0 {
+                return Color::zero();
+            }
+            let eta = lobes.eta;
+            let wm = match Self::transmission_half_vector(wo, wi, eta) {
+                Some(wm) => wm,
+                None => return Color::zero(),
+            };
+            let f = fresnel::dielectric(wo.dot(&wm), eta);
+            let denom = (wi.dot(&wm) + wo.dot(&wm) / eta).powi(2) * cos_o;
+            let value = lobes.distribution.d(&wm)
+                * (1.0 - f)
+                * lobes.distribution.g(wo, wi)
+                * (wi.dot(&wm) * wo.dot(&wm) / denom).abs()
+                / (eta * eta);
+            return lobes.base * (lobes.transmission_weight * value);
+        }
+
+        let wh = (*wo + *wi).unit_vector();
+        let cos_d = wi.dot(&wh);
+        let mut f = Color::zero();
+
+        if lobes.diffuse_weight > 0.0 {
+            // Burley diffuse with grazing retro-reflection, plus sheen.
+            let fl = schlick_weight(cos_i);
+            let fv = schlick_weight(cos_o);
+            let fd90 = 0.5 + 2.0 * lobes.roughness * cos_d * cos_d;
+            let fd = (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv);
+            let sheen = lobes.sheen_color * schlick_weight(cos_d);
+            f += (lobes.base * (fd / PI) + sheen) * lobes.diffuse_weight;
+        }
+
+        let fresnel_spec = lerp_color(
+            lobes.specular_f0,
+            Color::new(1.0, 1.0, 1.0),
+            schlick_weight(cos_d),
+        );
+        let spec = lobes.distribution.d(&wh) * lobes.distribution.g(wo, wi) / (4.0 * cos_o * cos_i);
+        f += fresnel_spec * spec;
+
+        if lobes.clearcoat > 0.0 {
+            let coat_masking = TrowbridgeReitz::new(0.25);
+            let d = gtr1(wh.z(), lobes.clearcoat_alpha);
+            let fr = 0.04 + 0.96 * schlick_weight(cos_d);
+            let g = coat_masking.g1(wo) * coat_masking.g1(wi);
+            let coat = 0.25 * lobes.clearcoat * d * fr * g / (4.0 * cos_o * cos_i);
+            f += Color::new(coat, coat, coat);
+        }
+
+        f * cos_i
+    }
+
+    fn pdf_local(lobes: &Lobes, probs: &LobeProbabilities, wo: &Vec3, wi: &Vec3) -> f64 {
+        let cos_o = wo.z();
+        let cos_i = wi.z();
+        if cos_o <= 0.0 || cos_i == 0.0 {
+            return 0.0;
+        }
+
+        if cos_i < 0.0 {
+            if probs.transmission <= 0.0 {
+                return 0.0;
+            }
+            let eta = lobes.eta;
+            let wm = match Self::transmission_half_vector(wo, wi, eta) {
+                Some(wm) => wm,
+                None => return 0.0,
+            };
+            let denom = (wi.dot(&wm) + wo.dot(&wm) / eta).powi(2);
+            let dwm_dwi = wi.dot(&wm).abs() / denom;
+            return probs.transmission * lobes.distribution.pdf(wo, &wm) * dwm_dwi;
+        }
+
+        let wh = (*wo + *wi).unit_vector();
+        let wo_dot_h = wo.dot(&wh).abs();
+        let mut pdf = probs.diffuse * cos_i / PI;
+        pdf += probs.specular * lobes.distribution.pdf(wo, &wh) / (4.0 * wo_dot_h);
+        if probs.clearcoat > 0.0 {
+            pdf += probs.clearcoat * gtr1(wh.z(), lobes.clearcoat_alpha) * wh.z()
+                / (4.0 * wo_dot_h);
+        }
+        pdf
+    }
+
+    fn local_directions(r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> (Vec3, Vec3) {
+        let frame = Onb::new(&rec.normal);
+        (
+            frame.to_local(&-r_in.direction().unit_vector()),
+            frame.to_local(&direction.unit_vector()),
+        )
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let lobes = self.lobes(rec);
+        let frame = Onb::new(&rec.normal);
+        let wo = frame.to_local(&-r_in.direction().unit_vector());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let probs = Self::probabilities(&lobes, &wo)?;
+
+        let u = random_double();
+        let wi = if u < probs.diffuse {
+            Vec3::random_cosine_direction()
+        } else if u < probs.diffuse + probs.specular {
+            let wm = lobes
+                .distribution
+                .sample_wm(&wo, random_double(), random_double());
+            let wi = (-wo).reflect(&wm);
+            // Below the horizon only the transmission lobe has density, so
+            // a reflection that ends up there can't be weighted correctly.
+            if wi.z() <= 0.0 {
+                return None;
+            }
+            wi
+        } else if u < probs.diffuse + probs.specular + probs.clearcoat {
+            let wh = sample_gtr1(lobes.clearcoat_alpha);
+            let wi = (-wo).reflect(&wh);
+            if wi.z() <= 0.0 {
+                return None;
+            }
+            wi
+        } else {
+            let wm = lobes
+                .distribution
+                .sample_wm(&wo, random_double(), random_double());
+            let cos_i = wo.dot(&wm);
+            let eta = lobes.eta;
+            let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+            if sin2_t >= 1.0 {
+                return None;
+            }
+            let cos_t = (1.0 - sin2_t).sqrt();
+            let wi = -wo / eta + wm * (cos_i / eta - cos_t);
+            // A grazing microfacet can bend the ray back above the horizon,
+            // where transmission has no density.
+            if wi.z() >= 0.0 {
+                return None;
+            }
+            wi
+        };
+
+        let pdf = Self::pdf_local(&lobes, &probs, &wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        let weight = Self::eval_local(&lobes, &wo, &wi) / pdf;
+        Some((weight, Ray::new(rec.p, frame.transform(&wi))))
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let lobes = self.lobes(rec);
+        let (wo, wi) = Self::local_directions(r_in, rec, direction);
+        Self::eval_local(&lobes, &wo, &wi)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let lobes = self.lobes(rec);
+        let (wo, wi) = Self::local_directions(r_in, rec, direction);
+        match Self::probabilities(&lobes, &wo) {
+            Some(probs) => Self::pdf_local(&lobes, &probs, &wo, &wi),
+            None => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Point3;
+
+    /// Checks that the average weight of `scatter` matches the integral of
+    /// `eval` over all directions, estimated with uniform directions, on each
+    /// side of the surface. Samples that `scatter` weighs wrongly, or keeps on
+    /// the wrong side, skew it.
+    fn check_against_eval(params: PrincipledParams, r_in: Ray, tolerance: f64) {
+        let mat = Principled::new(params);
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let mut rec = HitRecord::new(Point3::zero(), Vec3::zero(), mat.clone(), 1.0);
+        rec.set_face_normal(&r_in, normal);
+
+        let n = 400000;
+        let side = |direction: &Vec3| usize::from(direction.dot(&normal) < 0.0);
+        let mut sampled = [0.0; 2];
+        let mut uniform = [0.0; 2];
+        for _ in 0..n {
+            if let Some((weight, scattered)) = mat.scatter(&r_in, &rec) {
+                sampled[side(&scattered.direction())] += luminance(weight);
+            }
+            let direction = Vec3::random_unit_vector();
+            uniform[side(&direction)] += luminance(mat.eval(&r_in, &rec, &direction)) * 4.0 * PI;
+        }
+        let total: f64 = sampled.iter().sum::<f64>() / n as f64;
+        assert!(total > 0.0 && total < 1.1);
+        for (sampled, uniform) in sampled.iter().zip(uniform) {
+            let (sampled, uniform) = (sampled / n as f64, uniform / n as f64);
+            assert!((sampled - uniform).abs() < tolerance, "{sampled} vs {uniform}");
+        }
+    }
+
+    fn oblique() -> Ray {
+        Ray::new(Point3::new(-1.0, 2.0, 0.5), Vec3::new(1.0, -2.0, -0.5))
+    }
+
+    /// Barely above the surface, where rough lobes spill below the horizon.
+    fn grazing() -> Ray {
+        Ray::new(Point3::new(-1.0, 0.05, 0.0), Vec3::new(1.0, -0.05, 0.0))
+    }
+
+    #[test]
+    fn test_default_matches_eval() {
+        check_against_eval(PrincipledParams::default(), oblique(), 0.01);
+    }
+
+    #[test]
+    fn test_metal_with_clearcoat_matches_eval() {
+        let params = PrincipledParams {
+            metallic: SolidColor::gray(1.0),
+            roughness: SolidColor::gray(0.5),
+            clearcoat: SolidColor::gray(1.0),
+            ..PrincipledParams::default()
+        };
+        check_against_eval(params, oblique(), 0.02);
+    }
+
+    #[test]
+    fn test_transmission_matches_eval() {
+        let params = PrincipledParams {
+            base_color: SolidColor::gray(1.0),
+            roughness: SolidColor::gray(0.8),
+            transmission: SolidColor::gray(1.0),
+            ..PrincipledParams::default()
+        };
+        check_against_eval(params, oblique(), 0.02);
+    }
+
+    #[test]
+    fn test_grazing_scatter_matches_eval() {
+        // Rough reflections at grazing angles often point below the surface,
+        // where only transmission has density, and rough refractions can
+        // point above it; neither must be kept.
+        let params = PrincipledParams {
+            base_color: SolidColor::gray(1.0),
+            roughness: SolidColor::gray(1.0),
+            transmission: SolidColor::gray(1.0),
+            clearcoat: SolidColor::gray(1.0),
+            clearcoat_gloss: SolidColor::gray(0.0),
+            ..PrincipledParams::default()
+        };
+        check_against_eval(params, grazing(), 0.01);
+    }
+
+    #[test]
+    fn test_gltf_mapping_reads_packed_channels() {
+        let packed = SolidColor::new(Color::new(0.0, 0.4, 1.0));
+        let params = PrincipledParams::from_gltf(
+            Color::new(1.0, 0.5, 0.5),
+            None,
+            0.5,
+            1.0,
+            Some(packed),
+        );
+        let p = Point3::zero();
+        assert!((params.metallic.scalar(0.0, 0.0, &p) - 0.5).abs() < 1e-10);
+        assert!((params.roughness.scalar(0.0, 0.0, &p) - 0.4).abs() < 1e-10);
+    }
+}
@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+/// Settings for rendering in passes, rewriting the output after each one so
+/// a long render can be looked at while it runs. Rendering stops at the first
+/// limit reached, and goes on until interrupted if none is set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progressive {
+    /// Samples added to every pixel in each pass.
+    pub samples_per_pass: u32,
+    pub time_limit: Option<Duration>,
+    /// Noise level, as measured by `Film::noise_level`, to stop at.
+    pub target_noise: Option<f64>,
+    pub max_samples: Option<u32>,
+}
+
+impl Default for Progressive {
+    fn default() -> Self {
+        Self {
+            samples_per_pass: 16,
+            time_limit: None,
+            target_noise: None,
+            max_samples: None,
+        }
+    }
+}
+
+impl Progressive {
+    /// Whether to stop after a pass, given the samples per pixel taken so
+    /// far, the time spent and the noise left.
+    pub fn is_done(&self, samples: u32, elapsed: Duration, noise: f64) -> bool {
+        self.time_limit.is_some_and(|limit| elapsed >= limit)
+            || self.target_noise.is_some_and(|target| noise <= target)
+            || self.max_samples.is_some_and(|max| samples >= max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stops_at_first_limit() {
+        let progressive = Progressive {
+            time_limit: Some(Duration::from_secs(60)),
+            target_noise: Some(0.01),
+            ..Progressive::default()
+        };
+        assert!(!progressive.is_done(64, Duration::from_secs(30), 0.05));
+        assert!(progressive.is_done(64, Duration::from_secs(61), 0.05));
+        assert!(progressive.is_done(64, Duration::from_secs(30), 0.005));
+
+        let endless = Progressive::default();
+        assert!(!endless.is_done(1 << 20, Duration::from_secs(1 << 20), 0.0));
+    }
+}
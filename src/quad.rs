@@ -0,0 +1,155 @@
+use crate::color::luminance;
+use crate::hittable::{HitRecord, Hittable};
+use crate::light::{Light, QuadLight};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3};
+use std::sync::Arc;
+
+/// A flat parallelogram with corner `q` and edges `u` and `v`. Its outward
+/// normal is `u × v`, the side a front-facing emitter lights.
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    mat: Arc<dyn Material>,
+    normal: Vec3,
+    d: f64,
+    w: Vec3,
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, material: Arc<dyn Material>) -> Self {
+        let n = u.cross(&v);
+        let normal = n.unit_vector();
+        Self {
+            q,
+            u,
+            v,
+            mat: material,
+            normal,
+            d: normal.dot(&q),
+            w: n / n.dot(&n),
+        }
+    }
+
+    fn center(&self) -> Point3 {
+        self.q + (self.u + self.v) * 0.5
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let denom = self.normal.dot(&ray.direction());
+        if denom.abs() < 1e-8 {
+            return false;
+        }
+
+        let t = (self.d - self.normal.dot(&ray.origin())) / denom;
+        if t <= t_min || t_max <= t {
+            return false;
+        }
+
+        let p = ray.at(t);
+        let planar = p - self.q;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return false;
+        }
+
+        rec.t = t;
+        rec.p = p;
+        rec.set_face_normal(ray, self.normal);
+        (rec.u, rec.v) = (alpha, beta);
+        rec.mat = self.mat.clone();
+
+        true
+    }
+
+    fn collect_lights(&self, lights: &mut Vec<Light>) {
+        if self.u.cross(&self.v).length_squared() <= 0.0 {
+            return;
+        }
+
+        // Probe the emission seen from either side of the center.
+        let center = self.center();
+        let probe_side = |side: Vec3| {
+            let probe = Ray::new(center + side, -side);
+            let mut rec = HitRecord::new(center, side, self.mat.clone(), 1.0);
+            rec.set_face_normal(&probe, self.normal);
+            self.mat.emitted(&probe, &rec)
+        };
+        let front = probe_side(self.normal);
+        let back = probe_side(-self.normal);
+
+        if luminance(front) > 0.0 || luminance(back) > 0.0 {
+            lights.push(QuadLight::new(self.q, self.u, self.v, front, back).into());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::mix::TwoSided;
+    use crate::vec3::Color;
+
+    fn ceiling(mat: Arc<dyn Material>) -> Quad {
+        // Faces down, towards the origin.
+        Quad::new(
+            Point3::new(-1.0, 4.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            mat,
+        )
+    }
+
+    #[test]
+    fn test_quad_hit_and_bounds() {
+        let quad = ceiling(DiffuseLight::new(Color::new(1.0, 1.0, 1.0)));
+        let mut rec = HitRecord::new(Point3::zero(), Vec3::zero(), quad.mat.clone(), 0.0);
+
+        let up = Ray::new(Point3::zero(), Vec3::new(0.0, 1.0, 0.0));
+        assert!(quad.hit(&up, 0.001, f64::INFINITY, &mut rec));
+        assert!((rec.t - 4.0).abs() < 1e-10);
+        assert!(rec.front_face);
+
+        let beside = Ray::new(Point3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert!(!quad.hit(&beside, 0.001, f64::INFINITY, &mut rec));
+    }
+
+    #[test]
+    fn test_single_sided_quad_light_keeps_its_side() {
+        let mut lights = Vec::new();
+        ceiling(DiffuseLight::single_sided(Color::new(4.0, 4.0, 4.0))).collect_lights(&mut lights);
+        assert_eq!(lights.len(), 1);
+
+        let light = lights[0];
+        assert!(light.sample(Point3::zero()).is_some());
+        assert!(light.sample(Point3::new(0.0, 8.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn test_quad_light_takes_each_sides_radiance() {
+        let dim = DiffuseLight::new(Color::new(1.0, 1.0, 1.0));
+        let bright = DiffuseLight::new(Color::new(3.0, 3.0, 3.0));
+        let below = Point3::zero();
+        let above = Point3::new(0.0, 8.0, 0.0);
+
+        // Only the back, facing up, emits.
+        let mut lights = Vec::new();
+        let back_only = TwoSided::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)), dim.clone());
+        ceiling(back_only).collect_lights(&mut lights);
+        assert_eq!(lights.len(), 1);
+        assert!(lights[0].sample(below).is_none());
+        assert!(lights[0].sample(above).is_some());
+
+        // Each side counts towards the power with its own radiance.
+        let mut lights = Vec::new();
+        ceiling(TwoSided::new(dim.clone(), bright)).collect_lights(&mut lights);
+        ceiling(TwoSided::new(dim.clone(), dim)).collect_lights(&mut lights);
+        assert!((lights[0].power() - 2.0 * lights[1].power()).abs() < 1e-10);
+    }
+}
@@ -4,6 +4,7 @@ use crate::vec3::{Point3, Vec3};
 pub struct Ray {
     orig: Point3,
     dir: Vec3,
+    wavelength: Option<f64>,
 }
 
 impl Ray {
@@ -11,9 +12,20 @@ impl Ray {
         Ray {
             orig: origin,
             dir: direction,
+            wavelength: None,
         }
     }
 
+    /// Tags the ray with the hero wavelength (in nm) of a spectral path.
+    pub fn with_wavelength(mut self, wavelength: Option<f64>) -> Ray {
+        self.wavelength = wavelength;
+        self
+    }
+
+    pub fn wavelength(&self) -> Option<f64> {
+        self.wavelength
+    }
+
     pub fn origin(&self) -> Point3 {
         self.orig
     }
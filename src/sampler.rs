@@ -0,0 +1,505 @@
+use rand::Rng;
+
+/// Source of the random numbers for one pixel sample at a time. Each call
+/// draws the next dimension, so a sampler can spread the numbers of the same
+/// dimension evenly over all the samples of a pixel.
+pub trait Sampler {
+    /// Begins sample `index` of pixel `(x, y)`, going back to the first
+    /// dimension.
+    fn start_pixel_sample(&mut self, x: i32, y: i32, index: u32);
+
+    fn get_1d(&mut self) -> f64;
+
+    /// Two dimensions meant to be used together, like a point in the pixel
+    /// or on the lens.
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+/// Which sampler a camera renders with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplerKind {
+    /// Unrelated random numbers for every sample.
+    Independent,
+    /// One jittered sample in each cell of a grid per dimension, with the
+    /// cells shuffled differently for each pixel and dimension.
+    Stratified,
+    /// Halton sequence, scrambled differently for each pixel.
+    Halton,
+    /// Sobol sequence with hash-based Owen scrambling, padded to any number
+    /// of dimensions by shuffling 2D Sobol points.
+    Sobol,
+}
+
+impl SamplerKind {
+    /// Builds the sampler for a pass of `samples_per_pixel` samples starting
+    /// at sample `first_sample`. With a seed, every sample of every pixel
+    /// draws from its own stream derived from the seed, so renders come out
+    /// the same whatever order the pixels are visited in.
+    pub fn build(
+        self,
+        first_sample: u32,
+        samples_per_pixel: i32,
+        seed: Option<u64>,
+    ) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(
+                first_sample,
+                samples_per_pixel,
+                seed,
+            )),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+}
+
+/// Uniform random numbers, from the thread's generator or, when seeded,
+/// from a SplitMix64 stream restarted for each pixel sample.
+struct RandomStream {
+    seed: Option<u64>,
+    state: u64,
+}
+
+impl RandomStream {
+    fn new(seed: Option<u64>) -> Self {
+        Self {
+            seed,
+            state: seed.map_or(0, mix_bits),
+        }
+    }
+
+    fn start(&mut self, pixel: (i32, i32), index: u32) {
+        if let Some(seed) = self.seed {
+            self.state = mix_bits(dimension_seed(seed, pixel, index) ^ 0x5851_f42d_4c95_7f2d);
+        }
+    }
+
+    fn next(&mut self) -> f64 {
+        if self.seed.is_none() {
+            return rand::thread_rng().gen();
+        }
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Integer in [0, 2^32) to a float in [0, 1).
+fn to_unit(bits: u32) -> f64 {
+    bits as f64 / (1u64 << 32) as f64
+}
+
+/// Seed for one dimension of one pixel's samples.
+fn dimension_seed(seed: u64, pixel: (i32, i32), dimension: u32) -> u64 {
+    let h = mix_bits(seed ^ (pixel.0 as u32 as u64 | (pixel.1 as u32 as u64) << 32));
+    mix_bits(h ^ dimension as u64)
+}
+
+/// Finalizer of MurmurHash3, scrambling all bits of `v`.
+fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5_d329_728e_a185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81da_def4_bc2d_d44d);
+    v ^= v >> 33;
+    v
+}
+
+pub struct IndependentSampler {
+    random: RandomStream,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: Option<u64>) -> Self {
+        Self {
+            random: RandomStream::new(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x: i32, y: i32, index: u32) {
+        self.random.start((x, y), index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.random.next()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.random.next(), self.random.next())
+    }
+}
+
+/// Stratifies the samples of one pass: the pass's samples fall in different
+/// cells only once all of them are taken, so passes shouldn't be cut short.
+pub struct StratifiedSampler {
+    first_sample: u32,
+    samples_per_pixel: u32,
+    /// Grid used for 2D dimensions, with at least as many cells as samples.
+    x_strata: u32,
+    y_strata: u32,
+    seed: u64,
+    random: RandomStream,
+    pixel: (i32, i32),
+    index: u32,
+    dimension: u32,
+}
+
+impl StratifiedSampler {
+    pub fn new(first_sample: u32, samples_per_pixel: i32, seed: Option<u64>) -> Self {
+        let samples_per_pixel = samples_per_pixel.max(1) as u32;
+        let x_strata = (samples_per_pixel as f64).sqrt().ceil() as u32;
+        let y_strata = samples_per_pixel.div_ceil(x_strata);
+        Self {
+            first_sample,
+            samples_per_pixel,
+            x_strata,
+            y_strata,
+            seed: seed.unwrap_or(0),
+            random: RandomStream::new(seed),
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// Cell of the current sample among `count`, shuffled by the seed of the
+    /// current dimension and pass.
+    fn stratum(&mut self, count: u32) -> u32 {
+        let pass_seed = self.seed ^ mix_bits(self.first_sample as u64);
+        let seed = dimension_seed(pass_seed, self.pixel, self.dimension) as u32;
+        self.dimension += 1;
+        let position = self.index.wrapping_sub(self.first_sample) % count;
+        permutation_element(position, count, seed)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: i32, y: i32, index: u32) {
+        self.pixel = (x, y);
+        self.index = index;
+        self.dimension = 0;
+        self.random.start((x, y), index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let stratum = self.stratum(self.samples_per_pixel);
+        (stratum as f64 + self.random.next()) / self.samples_per_pixel as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let stratum = self.stratum(self.x_strata * self.y_strata);
+        (
+            ((stratum % self.x_strata) as f64 + self.random.next()) / self.x_strata as f64,
+            ((stratum / self.x_strata) as f64 + self.random.next()) / self.y_strata as f64,
+        )
+    }
+}
+
+/// Element `i` of a pseudo-random permutation of [0, `l`) chosen by `p`,
+/// from Kensler's "Correlated Multi-Jittered Sampling".
+fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            return (i.wrapping_add(p)) % l;
+        }
+    }
+}
+
+pub struct HaltonSampler {
+    /// Prime base of each dimension. Dimensions past the last prime fall
+    /// back to independent numbers.
+    bases: Vec<u32>,
+    seed: u64,
+    random: RandomStream,
+    pixel: (i32, i32),
+    index: u32,
+    dimension: u32,
+}
+
+impl HaltonSampler {
+    const DIMENSIONS: usize = 128;
+
+    pub fn new(seed: Option<u64>) -> Self {
+        let mut bases = Vec::with_capacity(Self::DIMENSIONS);
+        let mut candidate = 2;
+        while bases.len() < Self::DIMENSIONS {
+            if bases.iter().all(|&p| candidate % p != 0) {
+                bases.push(candidate);
+            }
+            candidate += 1;
+        }
+        Self {
+            bases,
+            seed: seed.unwrap_or(0),
+            random: RandomStream::new(seed),
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+/// Digits of `index` in base `base` mirrored around the decimal point, with
+/// each digit permuted depending on the digits before it (Owen scrambling).
+/// Unscrambled Halton dimensions with large bases are strongly correlated.
+fn scrambled_radical_inverse(base: u32, mut index: u32, seed: u64) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut factor = inverse_base;
+    let mut value = 0.0;
+    let mut prefix = 0u64;
+    // Trailing zero digits are scrambled too, down to the float's precision.
+    while factor > 1e-16 {
+        let digit = permutation_element(index % base, base, mix_bits(seed ^ prefix) as u32);
+        value += digit as f64 * factor;
+        prefix = prefix
+            .wrapping_mul(base as u64)
+            .wrapping_add(digit as u64 + 1);
+        index /= base;
+        factor *= inverse_base;
+    }
+    value.min(1.0 - f64::EPSILON)
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: i32, y: i32, index: u32) {
+        self.pixel = (x, y);
+        self.index = index;
+        self.dimension = 0;
+        self.random.start((x, y), index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        let base = match self.bases.get(dimension as usize) {
+            Some(&base) => base,
+            None => return self.random.next(),
+        };
+        scrambled_radical_inverse(base, self.index, dimension_seed(self.seed, self.pixel, dimension))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+pub struct SobolSampler {
+    seed: u64,
+    random: RandomStream,
+    pixel: (i32, i32),
+    index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub fn new(seed: Option<u64>) -> Self {
+        Self {
+            seed: seed.unwrap_or(0),
+            random: RandomStream::new(seed),
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// Sample index shuffled for the current dimension, so every dimension
+    /// or pair of dimensions pairs up the points differently.
+    fn shuffled_index(&self, seed: u64) -> u32 {
+        nested_uniform_scramble(self.index, seed as u32)
+    }
+}
+
+/// Owen scrambling of the bits of `x`, from Burley's "Practical Hash-based
+/// Owen Scrambling".
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+/// Second dimension of the Sobol sequence; the first is `index` with its
+/// bits reversed.
+fn sobol_second(mut index: u32) -> u32 {
+    let mut direction = 1u32 << 31;
+    let mut value = 0;
+    while index > 0 {
+        if index & 1 == 1 {
+            value ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    value
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: i32, y: i32, index: u32) {
+        self.pixel = (x, y);
+        self.index = index;
+        self.dimension = 0;
+        self.random.start((x, y), index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let seed = dimension_seed(self.seed, self.pixel, self.dimension);
+        self.dimension += 1;
+        let x = self.shuffled_index(seed).reverse_bits();
+        to_unit(nested_uniform_scramble(x, (seed >> 32) as u32))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let seed = dimension_seed(self.seed, self.pixel, self.dimension);
+        self.dimension += 2;
+        let index = self.shuffled_index(seed);
+        let x = nested_uniform_scramble(index.reverse_bits(), (seed >> 32) as u32);
+        let y = nested_uniform_scramble(sobol_second(index), mix_bits(seed) as u32);
+        (to_unit(x), to_unit(y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [SamplerKind; 4] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+    ];
+
+    /// Mean squared error over many pixels of estimating the integral of a
+    /// smooth function over the unit square.
+    fn pixel_error(kind: SamplerKind, samples_per_pixel: i32) -> f64 {
+        let mut sampler = kind.build(0, samples_per_pixel, None);
+        let exact = (1.0 - 1.0f64.cos()) * 2.0f64.sin() / 2.0;
+        let pixels = 200;
+        let mut total = 0.0;
+        for p in 0..pixels {
+            let mut sum = 0.0;
+            for s in 0..samples_per_pixel {
+                sampler.start_pixel_sample(p, 7, s as u32);
+                let (x, y) = sampler.get_2d();
+                sum += x.sin() * (2.0 * y).cos();
+            }
+            let error = sum / samples_per_pixel as f64 - exact;
+            total += error * error;
+        }
+        total / pixels as f64
+    }
+
+    #[test]
+    fn test_samples_in_unit_interval() {
+        for kind in KINDS {
+            let mut sampler = kind.build(0, 16, None);
+            for s in 0..16 {
+                sampler.start_pixel_sample(3, -2, s);
+                for _ in 0..200 {
+                    let u = sampler.get_1d();
+                    let (a, b) = sampler.get_2d();
+                    assert!((0.0..1.0).contains(&u), "{kind:?} gave {u}");
+                    assert!((0.0..1.0).contains(&a) && (0.0..1.0).contains(&b));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sobol_stays_stratified() {
+        let mut sampler = SobolSampler::new(None);
+        for dimension in 0..20 {
+            let mut seen = [false; 8];
+            for s in 0..8 {
+                sampler.start_pixel_sample(5, 9, s);
+                for _ in 0..dimension {
+                    sampler.get_1d();
+                }
+                seen[(sampler.get_1d() * 8.0) as usize] = true;
+            }
+            assert!(seen.iter().all(|&s| s));
+        }
+    }
+
+    #[test]
+    fn test_low_discrepancy_converges_faster() {
+        let independent = pixel_error(SamplerKind::Independent, 64);
+        for kind in [
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            let error = pixel_error(kind, 64);
+            assert!(
+                error < independent / 4.0,
+                "{kind:?}: {error} vs {independent}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_stratified_fills_every_pass() {
+        // A pass that doesn't start at a multiple of its size, like the
+        // clamped last pass of a progressive render.
+        for (first_sample, samples) in [(0, 4), (4, 4), (8, 3)] {
+            let mut sampler = SamplerKind::Stratified.build(first_sample, samples, Some(1));
+            let mut seen_1d = vec![false; samples as usize];
+            let mut seen_2d = [false; 4];
+            for s in first_sample..first_sample + samples as u32 {
+                sampler.start_pixel_sample(2, 5, s);
+                seen_1d[(sampler.get_1d() * samples as f64) as usize] = true;
+                let (x, y) = sampler.get_2d();
+                seen_2d[(x * 2.0) as usize + 2 * (y * 2.0) as usize] = true;
+            }
+            assert!(seen_1d.iter().all(|&s| s), "pass at {first_sample}");
+            assert_eq!(seen_2d.iter().filter(|&&s| s).count(), samples as usize);
+        }
+    }
+
+    #[test]
+    fn test_permutation_is_bijective() {
+        for l in [1, 5, 16, 33] {
+            let mut seen = vec![false; l as usize];
+            for i in 0..l {
+                seen[permutation_element(i, l, 0x1234_5678) as usize] = true;
+            }
+            assert!(seen.iter().all(|&s| s));
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use crate::color::xyz_to_rgb;
+use crate::light::SunLight;
+use crate::vec3::{Color, Vec3};
+use std::f64::consts::PI;
+
+/// Angular radius of the sun as seen from the earth, in degrees.
+const SUN_ANGULAR_RADIUS: f64 = 0.2665;
+
+/// Illuminance from the sun above the atmosphere, in klx, to match the sky's
+/// luminance in kcd/m².
+const SOLAR_ILLUMINANCE: f64 = 128.0;
+
+/// Wavelengths (in micrometres) used to evaluate the sun's transmittance for
+/// the red, green and blue channels.
+const RGB_WAVELENGTHS: [f64; 3] = [0.650, 0.570, 0.475];
+
+/// Coefficients of the Perez sky luminance distribution.
+#[derive(Debug, Clone, Copy)]
+struct Perez {
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    e: f64,
+}
+
+impl Perez {
+    fn new(turbidity: f64, coeffs: [[f64; 2]; 5]) -> Self {
+        let eval = |c: [f64; 2]| c[0] * turbidity + c[1];
+        Self {
+            a: eval(coeffs[0]),
+            b: eval(coeffs[1]),
+            c: eval(coeffs[2]),
+            d: eval(coeffs[3]),
+            e: eval(coeffs[4]),
+        }
+    }
+
+    /// Relative luminance for a view at zenith angle `theta` that is `gamma`
+    /// radians away from the sun.
+    fn eval(&self, theta: f64, gamma: f64) -> f64 {
+        let cos_gamma = gamma.cos();
+        (1.0 + self.a * (self.b / theta.cos()).exp())
+            * (1.0 + self.c * (self.d * gamma).exp() + self.e * cos_gamma * cos_gamma)
+    }
+}
+
+/// Analytic daylight sky after Preetham, Shirley and Smits (1999).
+///
+/// The sky is driven by the sun position and the atmospheric turbidity, and
+/// includes a sun disk whose color follows the same atmosphere. Directions are
+/// in world space with +y up; an azimuth of 0 points the sun towards -z and
+/// positive azimuths rotate it towards +x.
+///
+/// Sky and sun share one photometric scale, so the sun outshines the sky on
+/// a clear day as it should.
+#[derive(Debug, Clone)]
+pub struct Sky {
+    /// Scale applied to the sky and sun luminance (which is in kcd/m²).
+    intensity: f64,
+    /// Angular radius of the sun disk in degrees.
+    sun_angular_radius: f64,
+    sun_direction: Vec3,
+    /// Fraction of sunlight left after crossing the atmosphere.
+    sun_color: Color,
+    sun: SunLight,
+    theta_sun: f64,
+    perez_y: Perez,
+    perez_x: Perez,
+    perez_yc: Perez,
+    zenith_y: f64,
+    zenith_x: f64,
+    zenith_yc: f64,
+}
+
+impl Sky {
+    pub fn new(sun_elevation: f64, sun_azimuth: f64, turbidity: f64) -> Self {
+        let turbidity = turbidity.clamp(1.7, 10.0);
+        let elevation = sun_elevation.to_radians().clamp(0.0, PI / 2.0);
+        let azimuth = sun_azimuth.to_radians();
+        let theta_sun = PI / 2.0 - elevation;
+
+        let sun_direction = Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        );
+
+        let perez_y = Perez::new(
+            turbidity,
+            [
+                [0.1787, -1.4630],
+                [-0.3554, 0.4275],
+                [-0.0227, 5.3251],
+                [0.1206, -2.5771],
+                [-0.0670, 0.3703],
+            ],
+        );
+        let perez_x = Perez::new(
+            turbidity,
+            [
+                [-0.0193, -0.2592],
+                [-0.0665, 0.0008],
+                [-0.0004, 0.2125],
+                [-0.0641, -0.8989],
+                [-0.0033, 0.0452],
+            ],
+        );
+        let perez_yc = Perez::new(
+            turbidity,
+            [
+                [-0.0167, -0.2608],
+                [-0.0950, 0.0092],
+                [-0.0079, 0.2102],
+                [-0.0441, -1.6537],
+                [-0.0109, 0.0529],
+            ],
+        );
+
+        let chi = (4.0 / 9.0 - turbidity / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith_y = ((4.0453 * turbidity - 4.9710) * chi.tan() - 0.2155 * turbidity + 2.4192)
+            .max(0.0);
+
+        let t2 = turbidity * turbidity;
+        let th = [theta_sun.powi(3), theta_sun.powi(2), theta_sun, 1.0];
+        let dot4 = |c: [f64; 4]| c[0] * th[0] + c[1] * th[1] + c[2] * th[2] + c[3] * th[3];
+        let zenith_x = t2 * dot4([0.00166, -0.00375, 0.00209, 0.0])
+            + turbidity * dot4([-0.02903, 0.06377, -0.03202, 0.00394])
+            + dot4([0.11693, -0.21196, 0.06052, 0.25886]);
+        let zenith_yc = t2 * dot4([0.00275, -0.00610, 0.00317, 0.0])
+            + turbidity * dot4([-0.04214, 0.08970, -0.04153, 0.00516])
+            + dot4([0.15346, -0.26756, 0.06670, 0.26688]);
+
+        let sky = Self {
+            intensity: 0.02,
+            sun_angular_radius: SUN_ANGULAR_RADIUS,
+            sun_direction,
+            sun_color: sun_transmittance(theta_sun, turbidity),
+            sun: SunLight::new(sun_direction, SUN_ANGULAR_RADIUS, Color::zero()),
+            theta_sun,
+            perez_y,
+            perez_x,
+            perez_yc,
+            zenith_y,
+            zenith_x,
+            zenith_yc,
+        };
+        sky.with_sun()
+    }
+
+    /// The same sky scaled by `intensity`, which sets the exposure of both
+    /// sky and sun.
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self.with_sun()
+    }
+
+    /// The same sky with a sun disk `degrees` in radius. A larger sun gives
+    /// softer shadows at the same brightness.
+    pub fn with_sun_angular_radius(mut self, degrees: f64) -> Self {
+        self.sun_angular_radius = degrees.clamp(1e-3, 90.0);
+        self.with_sun()
+    }
+
+    /// Rebuilds the sun light after a setting changed. Its radiance spreads
+    /// the solar illuminance over the disk's solid angle.
+    fn with_sun(mut self) -> Self {
+        let solid_angle = 2.0 * PI * (1.0 - self.sun_angular_radius.to_radians().cos());
+        let radiance = self.sun_color * (SOLAR_ILLUMINANCE / solid_angle * self.intensity);
+        self.sun = SunLight::new(self.sun_direction, self.sun_angular_radius, radiance);
+        self
+    }
+
+    /// Unit vector pointing towards the sun.
+    pub fn sun_direction(&self) -> Vec3 {
+        self.sun_direction
+    }
+
+    /// Radiance of the sun disk, after passing through the atmosphere.
+    pub fn sun_radiance(&self) -> Color {
+        self.sun.radiance(&self.sun_direction)
+    }
+
+    /// The sun disk as a light, so it can be sampled directly instead of
+    /// only being found by rays that happen to hit it.
+    pub fn sun_light(&self) -> SunLight {
+        self.sun
+    }
+
+    /// Radiance arriving from `direction`, including the sun disk.
+    pub fn radiance(&self, direction: &Vec3) -> Color {
+        self.sky_radiance(direction) + self.sun.radiance(direction)
+    }
+
+    /// Radiance of the sky dome alone, without the sun disk. Directions below
+    /// the horizon reuse the horizon value.
+    pub fn sky_radiance(&self, direction: &Vec3) -> Color {
+        let unit_direction = direction.unit_vector();
+        let theta = unit_direction.y().max(0.001).acos();
+        let gamma = unit_direction
+            .dot(&self.sun_direction)
+            .clamp(-1.0, 1.0)
+            .acos();
+
+        let luminance = self.zenith_y * self.perez_y.eval(theta, gamma)
+            / self.perez_y.eval(0.0, self.theta_sun);
+        let x = self.zenith_x * self.perez_x.eval(theta, gamma)
+            / self.perez_x.eval(0.0, self.theta_sun);
+        let y = self.zenith_yc * self.perez_yc.eval(theta, gamma)
+            / self.perez_yc.eval(0.0, self.theta_sun);
+
+        xyy_to_rgb(x, y, luminance * self.intensity)
+    }
+}
+
+/// Fraction of sunlight reaching the ground for each RGB channel, from
+/// Rayleigh and aerosol (Ångström) extinction along the sun's optical path.
+fn sun_transmittance(theta_sun: f64, turbidity: f64) -> Color {
+    let theta_deg = theta_sun.to_degrees();
+    let relative_mass = 1.0 / (theta_sun.cos() + 0.15 * (93.885 - theta_deg).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let alpha = 1.3;
+
+    let channel = |lambda: f64| {
+        let rayleigh = (-0.008735 * lambda.powf(-4.08) * relative_mass).exp();
+        let aerosol = (-beta * lambda.powf(-alpha) * relative_mass).exp();
+        rayleigh * aerosol
+    };
+
+    Color::new(
+        channel(RGB_WAVELENGTHS[0]),
+        channel(RGB_WAVELENGTHS[1]),
+        channel(RGB_WAVELENGTHS[2]),
+    )
+}
+
+/// Converts CIE xyY chromaticity and luminance to linear sRGB.
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    if y <= 0.0 {
+        return Color::zero();
+    }
+
+    let big_x = x * luminance / y;
+    let big_z = (1.0 - x - y) * luminance / y;
+
+    let rgb = xyz_to_rgb(big_x, luminance, big_z);
+    Color::new(rgb.x().max(0.0), rgb.y().max(0.0), rgb.z().max(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sun_direction_follows_elevation() {
+        let sky = Sky::new(90.0, 0.0, 3.0);
+        assert!((sky.sun_direction().y() - 1.0).abs() < 1e-10);
+
+        let sky = Sky::new(0.0, 90.0, 3.0);
+        assert!((sky.sun_direction().x() - 1.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_sky_is_blue_away_from_sun() {
+        let sky = Sky::new(30.0, 0.0, 2.5);
+        let c = sky.radiance(&Vec3::new(0.0, 1.0, 1.0));
+        assert!(c.z() > c.x());
+    }
+
+    #[test]
+    fn test_sun_disk_is_brighter_than_sky() {
+        let sky = Sky::new(45.0, 30.0, 3.0);
+        let toward_sun = sky.radiance(&sky.sun_direction());
+        let away = sky.radiance(&Vec3::new(0.0, 1.0, 0.0));
+        assert!(toward_sun.y() > away.y());
+    }
+
+    #[test]
+    fn test_sun_outshines_sky_at_midday() {
+        use crate::color::luminance;
+
+        let sky = Sky::new(70.0, 0.0, 2.5);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let sun = sky.sun_light();
+        let cos_sun = sky.sun_direction().dot(&up);
+        let sun_irradiance =
+            luminance(sky.sun_radiance()) * cos_sun / sun.pdf(&sky.sun_direction());
+
+        // Midpoint rule over the upper hemisphere, weighted by the cosine.
+        let (n_theta, n_phi) = (64, 128);
+        let mut sky_irradiance = 0.0;
+        for i in 0..n_theta {
+            let theta = (i as f64 + 0.5) / n_theta as f64 * PI / 2.0;
+            for j in 0..n_phi {
+                let phi = (j as f64 + 0.5) / n_phi as f64 * 2.0 * PI;
+                let direction = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                let d_omega = theta.sin() * (PI / 2.0 / n_theta as f64) * (2.0 * PI / n_phi as f64);
+                sky_irradiance += luminance(sky.sky_radiance(&direction)) * theta.cos() * d_omega;
+            }
+        }
+        assert!(
+            sun_irradiance > 2.0 * sky_irradiance,
+            "sun {sun_irradiance}, sky {sky_irradiance}"
+        );
+
+        // A larger disk softens shadows without brightening the scene.
+        let wide = sky.clone().with_sun_angular_radius(2.0);
+        let wide_irradiance =
+            luminance(wide.sun_radiance()) * cos_sun / wide.sun_light().pdf(&wide.sun_direction());
+        assert!((wide_irradiance / sun_irradiance - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_low_sun_is_redder() {
+        let high = Sky::new(80.0, 0.0, 3.0).sun_radiance();
+        let low = Sky::new(5.0, 0.0, 3.0).sun_radiance();
+        assert!(low.z() / low.x() < high.z() / high.x());
+    }
+}
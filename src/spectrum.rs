@@ -0,0 +1,281 @@
+use crate::color::xyz_to_rgb;
+use crate::vec3::Color;
+use std::sync::OnceLock;
+
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+
+/// Number of wavelengths traced together along a path. Three lets the
+/// per-wavelength values of a path travel in a `Color`.
+pub const N_WAVELENGTHS: usize = 3;
+
+/// Integral of the CIE Y matching function over the visible range.
+const CIE_Y_INTEGRAL: f64 = 106.856895;
+
+/// Smits (1999) basis spectra, sampled at ten evenly spaced wavelengths
+/// between 380 and 720 nm.
+const SMITS_WHITE: [f64; 10] = [1.0, 1.0, 0.9999, 0.9993, 0.9992, 0.9998, 1.0, 1.0, 1.0, 1.0];
+const SMITS_CYAN: [f64; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0, 0.0, 0.0];
+const SMITS_MAGENTA: [f64; 10] = [1.0, 1.0, 0.9685, 0.2229, 0.0, 0.0458, 0.8369, 1.0, 1.0, 0.9959];
+const SMITS_YELLOW: [f64; 10] = [0.0001, 0.0, 0.1088, 0.6651, 1.0, 1.0, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED: [f64; 10] = [0.1012, 0.0515, 0.0, 0.0, 0.0, 0.0, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [f64; 10] = [0.0, 0.0, 0.0273, 0.7937, 1.0, 0.9418, 0.1719, 0.0, 0.0, 0.0025];
+const SMITS_BLUE: [f64; 10] = [1.0, 1.0, 0.8916, 0.3323, 0.0, 0.0, 0.0003, 0.0369, 0.0483, 0.0496];
+
+fn smits_basis(table: &[f64; 10], lambda: f64) -> f64 {
+    let x = ((lambda - 380.0) / (720.0 - 380.0) * 9.0).clamp(0.0, 9.0);
+    let i = (x.floor() as usize).min(8);
+    let t = x - i as f64;
+    table[i] * (1.0 - t) + table[i + 1] * t
+}
+
+/// Value at `lambda` (in nm) of a smooth spectrum whose color is `rgb`, using
+/// Smits' method. Works for reflectances and, scaled, for emission.
+pub fn rgb_to_spectrum(rgb: Color, lambda: f64) -> f64 {
+    let (r, g, b) = (rgb.x(), rgb.y(), rgb.z());
+    let s = |table: &[f64; 10]| smits_basis(table, lambda);
+
+    if r <= g && r <= b {
+        let mut v = r * s(&SMITS_WHITE);
+        if g <= b {
+            v += (g - r) * s(&SMITS_CYAN) + (b - g) * s(&SMITS_BLUE);
+        } else {
+            v += (b - r) * s(&SMITS_CYAN) + (g - b) * s(&SMITS_GREEN);
+        }
+        v
+    } else if g <= r && g <= b {
+        let mut v = g * s(&SMITS_WHITE);
+        if r <= b {
+            v += (r - g) * s(&SMITS_MAGENTA) + (b - r) * s(&SMITS_BLUE);
+        } else {
+            v += (b - g) * s(&SMITS_MAGENTA) + (r - b) * s(&SMITS_RED);
+        }
+        v
+    } else {
+        let mut v = b * s(&SMITS_WHITE);
+        if r <= g {
+            v += (r - b) * s(&SMITS_YELLOW) + (g - r) * s(&SMITS_GREEN);
+        } else {
+            v += (g - b) * s(&SMITS_YELLOW) + (r - g) * s(&SMITS_RED);
+        }
+        v
+    }
+}
+
+/// Piecewise Gaussian used by the analytic CIE fits.
+fn lobe(lambda: f64, mu: f64, sigma_low: f64, sigma_high: f64) -> f64 {
+    let sigma = if lambda < mu { sigma_low } else { sigma_high };
+    let t = (lambda - mu) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+/// CIE 1931 color matching functions, using the multi-lobe fit of Wyman,
+/// Sloan and Shirley (2013).
+pub fn cie_xyz(lambda: f64) -> (f64, f64, f64) {
+    let x = 1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * lobe(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8);
+    (x, y, z)
+}
+
+/// Density used to importance sample visible wavelengths (Radziszewski et
+/// al. 2009), concentrating samples where the eye is most sensitive.
+pub fn visible_wavelengths_pdf(lambda: f64) -> f64 {
+    if !(LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda) {
+        return 0.0;
+    }
+    0.0039398042 / (0.0072 * (lambda - 538.0)).cosh().powi(2)
+}
+
+pub fn sample_visible_wavelength(u: f64) -> f64 {
+    538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh()
+}
+
+/// Wavelengths carried by one path. The first is the hero wavelength, which
+/// decides wavelength-dependent scattering such as dispersion; the others
+/// are evenly offset from it and follow the same path.
+#[derive(Debug, Clone, Copy)]
+pub struct SampledWavelengths {
+    lambda: [f64; N_WAVELENGTHS],
+    pdf: [f64; N_WAVELENGTHS],
+}
+
+impl SampledWavelengths {
+    pub fn sample_visible(u: f64) -> Self {
+        let mut lambda = [0.0; N_WAVELENGTHS];
+        let mut pdf = [0.0; N_WAVELENGTHS];
+        for i in 0..N_WAVELENGTHS {
+            let up = (u + i as f64 / N_WAVELENGTHS as f64).fract();
+            lambda[i] = sample_visible_wavelength(up);
+            pdf[i] = visible_wavelengths_pdf(lambda[i]);
+        }
+        Self { lambda, pdf }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    pub fn lambda(&self, i: usize) -> f64 {
+        self.lambda[i]
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf[1..].iter().all(|&p| p == 0.0)
+    }
+
+    /// Drops all but the hero wavelength, for paths that have scattered in a
+    /// wavelength-dependent way and so are only valid for the hero.
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+        for p in self.pdf[1..].iter_mut() {
+            *p = 0.0;
+        }
+        self.pdf[0] /= N_WAVELENGTHS as f64;
+    }
+
+    /// Values of the spectrum for `rgb` at each sampled wavelength.
+    pub fn sample_rgb(&self, rgb: Color) -> Color {
+        Color::new(
+            rgb_to_spectrum(rgb, self.lambda[0]),
+            rgb_to_spectrum(rgb, self.lambda[1]),
+            rgb_to_spectrum(rgb, self.lambda[2]),
+        )
+    }
+
+    /// Converts per-wavelength radiance into a linear sRGB estimate.
+    pub fn to_rgb(&self, values: Color) -> Color {
+        let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+        for i in 0..N_WAVELENGTHS {
+            if self.pdf[i] == 0.0 {
+                continue;
+            }
+            let (cx, cy, cz) = cie_xyz(self.lambda[i]);
+            let v = values[i] / self.pdf[i];
+            x += v * cx;
+            y += v * cy;
+            z += v * cz;
+        }
+
+        let scale = 1.0 / (N_WAVELENGTHS as f64 * CIE_Y_INTEGRAL);
+        let rgb = xyz_to_rgb(x * scale, y * scale, z * scale);
+
+        // Balance so a spectrally flat white maps back to RGB white.
+        let white = WHITE_RGB.get_or_init(flat_spectrum_rgb);
+        Color::new(rgb.x() / white.x(), rgb.y() / white.y(), rgb.z() / white.z())
+    }
+}
+
+/// Linear sRGB color of a reflectance spectrum, given as a function of
+/// wavelength in nm, under equal-energy light. A perfect reflector is white.
+pub fn reflectance_to_rgb(reflectance: impl Fn(f64) -> f64) -> Color {
+    let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+    let (mut wx, mut wy, mut wz) = (0.0, 0.0, 0.0);
+    let mut lambda = LAMBDA_MIN;
+    while lambda <= LAMBDA_MAX {
+        let (cx, cy, cz) = cie_xyz(lambda);
+        let r = reflectance(lambda);
+        x += r * cx;
+        y += r * cy;
+        z += r * cz;
+        wx += cx;
+        wy += cy;
+        wz += cz;
+        lambda += 10.0;
+    }
+
+    let rgb = xyz_to_rgb(x, y, z);
+    let white = xyz_to_rgb(wx, wy, wz);
+    Color::new(
+        (rgb.x() / white.x()).clamp(0.0, 1.0),
+        (rgb.y() / white.y()).clamp(0.0, 1.0),
+        (rgb.z() / white.z()).clamp(0.0, 1.0),
+    )
+}
+
+static WHITE_RGB: OnceLock<Color> = OnceLock::new();
+
+/// Linear sRGB of a constant unit spectrum, integrated numerically.
+fn flat_spectrum_rgb() -> Color {
+    let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+    let mut lambda = LAMBDA_MIN;
+    while lambda <= LAMBDA_MAX {
+        let (cx, cy, cz) = cie_xyz(lambda);
+        x += cx;
+        y += cy;
+        z += cz;
+        lambda += 1.0;
+    }
+    xyz_to_rgb(x / CIE_Y_INTEGRAL, y / CIE_Y_INTEGRAL, z / CIE_Y_INTEGRAL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smits_white_is_flat() {
+        for lambda in [400.0, 500.0, 600.0, 700.0] {
+            let v = rgb_to_spectrum(Color::new(1.0, 1.0, 1.0), lambda);
+            assert!((v - 1.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_smits_red_is_long_wavelength() {
+        let red = Color::new(1.0, 0.0, 0.0);
+        assert!(rgb_to_spectrum(red, 650.0) > rgb_to_spectrum(red, 450.0));
+    }
+
+    #[test]
+    fn test_cie_y_integral() {
+        let mut total = 0.0;
+        let mut lambda = LAMBDA_MIN;
+        while lambda <= LAMBDA_MAX {
+            total += cie_xyz(lambda).1;
+            lambda += 1.0;
+        }
+        assert!((total - CIE_Y_INTEGRAL).abs() / CIE_Y_INTEGRAL < 0.02);
+    }
+
+    #[test]
+    fn test_sampled_wavelengths_in_range() {
+        for i in 0..100 {
+            let wl = SampledWavelengths::sample_visible(i as f64 / 100.0);
+            for j in 0..N_WAVELENGTHS {
+                assert!(wl.lambda(j) >= LAMBDA_MIN && wl.lambda(j) <= LAMBDA_MAX);
+            }
+        }
+    }
+
+    #[test]
+    fn test_white_round_trips() {
+        // Averaging many wavelength samples of a white spectrum gives white.
+        let n = 4000;
+        let mut total = Color::zero();
+        for i in 0..n {
+            let wl = SampledWavelengths::sample_visible((i as f64 + 0.5) / n as f64);
+            total += wl.to_rgb(wl.sample_rgb(Color::new(1.0, 1.0, 1.0)));
+        }
+        let average = total / n as f64;
+        assert!((average - Color::new(1.0, 1.0, 1.0)).length() < 0.05);
+    }
+
+    #[test]
+    fn test_reflectance_to_rgb() {
+        let white = reflectance_to_rgb(|_| 1.0);
+        assert!((white - Color::new(1.0, 1.0, 1.0)).length() < 1e-9);
+        let red = reflectance_to_rgb(|lambda| if lambda > 600.0 { 1.0 } else { 0.0 });
+        assert!(red.x() > red.y() && red.x() > red.z());
+    }
+
+    #[test]
+    fn test_terminate_secondary() {
+        let mut wl = SampledWavelengths::sample_visible(0.3);
+        wl.terminate_secondary();
+        assert!(wl.secondary_terminated());
+    }
+}
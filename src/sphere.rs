@@ -1,7 +1,10 @@
+use crate::color::luminance;
 use crate::hittable::{HitRecord, Hittable};
+use crate::light::{Light, SphereLight};
 use crate::material::Material;
 use crate::ray::Ray;
-use crate::vec3::Point3;
+use crate::vec3::{Point3, Vec3};
+use std::f64::consts::PI;
 use std::sync::Arc;
 
 pub struct Sphere {
@@ -18,6 +21,14 @@ impl Sphere {
             mat: material,
         }
     }
+
+    /// Texture coordinates for a point on the unit sphere: `u` runs around
+    /// the y axis starting from -x, `v` runs from the bottom pole to the top.
+    fn get_sphere_uv(p: &Point3) -> (f64, f64) {
+        let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+        let phi = (-p.z()).atan2(p.x()) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
 }
 
 impl Hittable for Sphere {
@@ -45,8 +56,27 @@ impl Hittable for Sphere {
         rec.p = ray.at(root);
         let outward_normal = (rec.p - self.center) / self.radius;
         rec.set_face_normal(ray, outward_normal);
+        (rec.u, rec.v) = Self::get_sphere_uv(&outward_normal);
         rec.mat = self.mat.clone();
 
         true
     }
+
+    fn collect_lights(&self, lights: &mut Vec<Light>) {
+        if self.radius <= 0.0 {
+            return;
+        }
+
+        // Probe the emission seen from just above the top of the sphere.
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let top = self.center + up * self.radius;
+        let probe = Ray::new(top + up, -up);
+        let mut rec = HitRecord::new(top, up, self.mat.clone(), 1.0);
+        rec.set_face_normal(&probe, up);
+
+        let radiance = self.mat.emitted(&probe, &rec);
+        if luminance(radiance) > 0.0 {
+            lights.push(SphereLight::new(self.center, self.radius, radiance).into());
+        }
+    }
 }
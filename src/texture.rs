@@ -0,0 +1,135 @@
+use crate::vec3::{Color, Point3};
+use std::sync::Arc;
+
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+
+    /// Reads the texture as a single value, taken from the red channel.
+    fn scalar(&self, u: f64, v: f64, p: &Point3) -> f64 {
+        self.value(u, v, p).x()
+    }
+}
+
+pub struct SolidColor {
+    albedo: Color,
+}
+
+impl SolidColor {
+    pub fn new(albedo: Color) -> Arc<Self> {
+        Arc::new(Self { albedo })
+    }
+
+    /// Texture holding the same value in every channel.
+    pub fn gray(value: f64) -> Arc<Self> {
+        Self::new(Color::new(value, value, value))
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.albedo
+    }
+}
+
+/// Solid 3D checker pattern alternating between two textures.
+pub struct CheckerTexture {
+    inv_scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Arc<Self> {
+        Arc::new(Self {
+            inv_scale: 1.0 / scale,
+            even,
+            odd,
+        })
+    }
+
+    pub fn from_colors(scale: f64, c1: Color, c2: Color) -> Arc<Self> {
+        Self::new(scale, SolidColor::new(c1), SolidColor::new(c2))
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let x = (self.inv_scale * p.x()).floor() as i64;
+        let y = (self.inv_scale * p.y()).floor() as i64;
+        let z = (self.inv_scale * p.z()).floor() as i64;
+
+        if (x + y + z) % 2 == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+/// Multiplies another texture by a constant color.
+pub struct ScaledTexture {
+    source: Arc<dyn Texture>,
+    scale: Color,
+}
+
+impl ScaledTexture {
+    pub fn new(source: Arc<dyn Texture>, scale: Color) -> Arc<Self> {
+        Arc::new(Self { source, scale })
+    }
+}
+
+impl Texture for ScaledTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.source.value(u, v, p) * self.scale
+    }
+}
+
+/// Extracts one channel of another texture, scaled by `factor`, as a gray
+/// value. Useful for packed maps such as glTF's metallic-roughness texture.
+pub struct ChannelTexture {
+    source: Arc<dyn Texture>,
+    channel: usize,
+    factor: f64,
+}
+
+impl ChannelTexture {
+    pub fn new(source: Arc<dyn Texture>, channel: usize, factor: f64) -> Arc<Self> {
+        Arc::new(Self {
+            source,
+            channel: channel.min(2),
+            factor,
+        })
+    }
+}
+
+impl Texture for ChannelTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let c = self.source.value(u, v, p)[self.channel] * self.factor;
+        Color::new(c, c, c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checker_alternates() {
+        let checker =
+            CheckerTexture::from_colors(1.0, Color::new(1.0, 1.0, 1.0), Color::zero());
+        let a = checker.value(0.0, 0.0, &Point3::new(0.5, 0.5, 0.5));
+        let b = checker.value(0.0, 0.0, &Point3::new(1.5, 0.5, 0.5));
+        assert_eq!(a.x(), 1.0);
+        assert_eq!(b.x(), 0.0);
+    }
+
+    #[test]
+    fn test_channel_texture() {
+        let packed = SolidColor::new(Color::new(0.1, 0.5, 0.9));
+        let roughness = ChannelTexture::new(packed.clone(), 1, 0.5);
+        let metallic = ChannelTexture::new(packed, 2, 1.0);
+        let p = Point3::zero();
+        assert!((roughness.scalar(0.0, 0.0, &p) - 0.25).abs() < 1e-10);
+        assert!((metallic.scalar(0.0, 0.0, &p) - 0.9).abs() < 1e-10);
+    }
+}
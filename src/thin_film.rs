@@ -0,0 +1,260 @@
+use crate::bababoi::random_double;
+use crate::fresnel;
+use crate::hittable::HitRecord;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::spectrum::reflectance_to_rgb;
+use crate::texture::{SolidColor, Texture};
+use crate::vec3::Color;
+use std::sync::{Arc, OnceLock};
+
+/// Wavelengths in nm at which a conductor's red, green and blue indices of
+/// refraction are given.
+const CHANNEL_WAVELENGTHS: [f64; 3] = [650.0, 550.0, 450.0];
+
+/// Thickest film the RGB reflectance table covers, in nanometers. Thicker
+/// films are looked up at this thickness; their interference colors have
+/// mostly washed out by then.
+const TABLE_MAX_THICKNESS: f64 = 2000.0;
+const TABLE_THICKNESS_STEPS: usize = 200;
+const TABLE_ANGLE_STEPS: usize = 64;
+
+/// What a thin film is deposited on.
+#[derive(Debug, Clone, Copy)]
+pub enum FilmSubstrate {
+    /// Smooth glass-like body with the given index of refraction. An index of
+    /// 1 gives a free-standing film such as a soap bubble.
+    Dielectric(f64),
+    /// Smooth metal with complex index of refraction `eta + i k`, given per
+    /// channel at 650, 550 and 450 nm. Other wavelengths interpolate linearly
+    /// between them and keep the end values beyond.
+    Conductor { eta: Color, k: Color },
+}
+
+/// Smooth surface covered by a thin transparent film, whose reflections
+/// interfere to give the iridescent colors of soap bubbles, oil slicks and
+/// coated lenses.
+///
+/// Spectral paths evaluate the film at their hero wavelength. RGB paths use
+/// the color of the film's full reflectance spectrum instead, looked up in a
+/// table over angle and thickness that is built on first use.
+pub struct ThinFilm {
+    substrate: FilmSubstrate,
+    film_ior: f64,
+    /// Film thickness in nanometers, read from the red channel.
+    thickness: Arc<dyn Texture>,
+    rgb_table: OnceLock<ReflectanceTable>,
+}
+
+impl ThinFilm {
+    pub fn new(substrate: FilmSubstrate, film_ior: f64, thickness_nm: f64) -> Arc<Self> {
+        Self::with_thickness_texture(substrate, film_ior, SolidColor::gray(thickness_nm))
+    }
+
+    /// Film whose thickness in nanometers varies over the surface.
+    pub fn with_thickness_texture(
+        substrate: FilmSubstrate,
+        film_ior: f64,
+        thickness: Arc<dyn Texture>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            substrate,
+            film_ior,
+            thickness,
+            rgb_table: OnceLock::new(),
+        })
+    }
+
+    /// Free-standing film of soapy water.
+    pub fn soap_bubble(thickness_nm: f64) -> Arc<Self> {
+        Self::new(FilmSubstrate::Dielectric(1.0), 1.33, thickness_nm)
+    }
+
+    /// Reflectance at one wavelength. Light inside a dielectric substrate
+    /// meets the film from the substrate side.
+    fn reflectance(&self, cos_theta: f64, front_face: bool, thickness: f64, lambda: f64) -> f64 {
+        match self.substrate {
+            FilmSubstrate::Dielectric(ior) => {
+                let (eta_i, eta_t) = if front_face { (1.0, ior) } else { (ior, 1.0) };
+                fresnel::thin_film(
+                    cos_theta,
+                    eta_i,
+                    self.film_ior,
+                    thickness,
+                    eta_t,
+                    0.0,
+                    lambda,
+                )
+            }
+            FilmSubstrate::Conductor { eta, k } => fresnel::thin_film(
+                cos_theta,
+                1.0,
+                self.film_ior,
+                thickness,
+                channel_at(eta, lambda),
+                channel_at(k, lambda),
+                lambda,
+            ),
+        }
+    }
+
+    /// Color of the film's reflectance spectrum, from the table.
+    fn reflectance_rgb(&self, cos_theta: f64, front_face: bool, thickness: f64) -> Color {
+        self.rgb_table
+            .get_or_init(|| ReflectanceTable::new(self))
+            .lookup(cos_theta, front_face, thickness)
+    }
+}
+
+/// Value of a per-channel quantity at `lambda`, interpolating between the
+/// channel wavelengths.
+fn channel_at(c: Color, lambda: f64) -> f64 {
+    let [red, green, blue] = CHANNEL_WAVELENGTHS;
+    if lambda >= red {
+        c.x()
+    } else if lambda >= green {
+        let t = (lambda - green) / (red - green);
+        c.y() + (c.x() - c.y()) * t
+    } else if lambda >= blue {
+        let t = (lambda - blue) / (green - blue);
+        c.z() + (c.y() - c.z()) * t
+    } else {
+        c.z()
+    }
+}
+
+/// RGB reflectance of a film on a grid of incident cosines and thicknesses,
+/// for light arriving from either side. Integrating the spectrum for every
+/// RGB scatter would cost dozens of Airy sums each time.
+struct ReflectanceTable {
+    front: Vec<Color>,
+    back: Vec<Color>,
+}
+
+impl ReflectanceTable {
+    fn new(film: &ThinFilm) -> Self {
+        let side = |front_face: bool| {
+            (0..=TABLE_THICKNESS_STEPS)
+                .flat_map(|t| (0..=TABLE_ANGLE_STEPS).map(move |c| (t, c)))
+                .map(|(t, c)| {
+                    let thickness =
+                        TABLE_MAX_THICKNESS * t as f64 / TABLE_THICKNESS_STEPS as f64;
+                    let cos_theta = c as f64 / TABLE_ANGLE_STEPS as f64;
+                    reflectance_to_rgb(|lambda| {
+                        film.reflectance(cos_theta, front_face, thickness, lambda)
+                    })
+                })
+                .collect()
+        };
+        Self {
+            front: side(true),
+            back: side(false),
+        }
+    }
+
+    /// Bilinearly interpolated reflectance.
+    fn lookup(&self, cos_theta: f64, front_face: bool, thickness: f64) -> Color {
+        let table = if front_face { &self.front } else { &self.back };
+        let c = cos_theta.clamp(0.0, 1.0) * TABLE_ANGLE_STEPS as f64;
+        let t = (thickness / TABLE_MAX_THICKNESS).clamp(0.0, 1.0) * TABLE_THICKNESS_STEPS as f64;
+        let (c0, t0) = (
+            (c as usize).min(TABLE_ANGLE_STEPS - 1),
+            (t as usize).min(TABLE_THICKNESS_STEPS - 1),
+        );
+        let (fc, ft) = (c - c0 as f64, t - t0 as f64);
+
+        let at = |t: usize, c: usize| table[t * (TABLE_ANGLE_STEPS + 1) + c];
+        let thin = at(t0, c0) * (1.0 - fc) + at(t0, c0 + 1) * fc;
+        let thick = at(t0 + 1, c0) * (1.0 - fc) + at(t0 + 1, c0 + 1) * fc;
+        thin * (1.0 - ft) + thick * ft
+    }
+}
+
+impl Material for ThinFilm {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let unit_direction = r_in.direction().unit_vector();
+        let cos_theta = (-unit_direction).dot(&rec.normal).min(1.0);
+        let thickness = self.thickness.scalar(rec.u, rec.v, &rec.p).max(0.0);
+
+        let reflectance = match r_in.wavelength() {
+            Some(lambda) => {
+                let r = self.reflectance(cos_theta, rec.front_face, thickness, lambda);
+                Color::new(r, r, r)
+            }
+            None => self.reflectance_rgb(cos_theta, rec.front_face, thickness),
+        };
+        let reflected = Ray::new(rec.p, unit_direction.reflect(&rec.normal));
+
+        let ior = match self.substrate {
+            FilmSubstrate::Dielectric(ior) => ior,
+            FilmSubstrate::Conductor { .. } => return Some((reflectance, reflected)),
+        };
+
+        let refraction_ratio = if rec.front_face { 1.0 / ior } else { ior };
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        if refraction_ratio * sin_theta > 1.0 {
+            return Some((Color::new(1.0, 1.0, 1.0), reflected));
+        }
+
+        // The film is too thin to displace the ray, so only the substrate
+        // bends it. Choose reflection by the mean reflectance and reweight.
+        let transmittance = Color::new(1.0, 1.0, 1.0) - reflectance;
+        let p_reflect =
+            ((reflectance.x() + reflectance.y() + reflectance.z()) / 3.0).clamp(1e-4, 1.0 - 1e-4);
+        if random_double() < p_reflect {
+            Some((reflectance / p_reflect, reflected))
+        } else {
+            let refracted = unit_direction.refract(&rec.normal, refraction_ratio);
+            Some((
+                transmittance / (1.0 - p_reflect),
+                Ray::new(rec.p, refracted),
+            ))
+        }
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn is_dispersive(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bubble_is_iridescent() {
+        let bubble = ThinFilm::soap_bubble(400.0);
+        let color = reflectance_to_rgb(|lambda| bubble.reflectance(1.0, true, 400.0, lambda));
+        let spread =
+            color.x().max(color.y()).max(color.z()) - color.x().min(color.y()).min(color.z());
+        assert!(spread > 0.01);
+    }
+
+    #[test]
+    fn test_zero_thickness_matches_bare_metal() {
+        let eta = Color::new(0.2, 0.9, 1.1);
+        let k = Color::new(3.9, 2.4, 2.1);
+        let film = ThinFilm::new(FilmSubstrate::Conductor { eta, k }, 1.5, 0.0);
+        for (lambda, eta, k) in [(650.0, eta.x(), k.x()), (550.0, eta.y(), k.y())] {
+            let r = film.reflectance(1.0, true, 0.0, lambda);
+            let expected = fresnel::conductor_scalar(1.0, eta, k);
+            assert!((r - expected).abs() < 1e-9);
+        }
+        assert!((channel_at(eta, 600.0) - 0.55).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_table_matches_integrated_color() {
+        let bubble = ThinFilm::soap_bubble(0.0);
+        for (cos_theta, thickness) in [(0.93, 412.0), (0.31, 1234.5)] {
+            let table = bubble.reflectance_rgb(cos_theta, true, thickness);
+            let direct =
+                reflectance_to_rgb(|lambda| bubble.reflectance(cos_theta, true, thickness, lambda));
+            assert!((table - direct).length() < 0.02, "{table:?} vs {direct:?}");
+        }
+    }
+}
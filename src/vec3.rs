@@ -1,7 +1,7 @@
 use core::fmt;
-use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use std::ops::{Add, AddAssign, Div, DivAssign, Index, Mul, MulAssign, Neg, Sub, SubAssign};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Vec3 {
     e: [f64; 3],
 }
@@ -64,6 +64,19 @@ impl Vec3 {
         }
     }
 
+    /// Cosine-weighted direction on the hemisphere around +z.
+    pub fn random_cosine_direction() -> Vec3 {
+        let r1 = crate::bababoi::random_double();
+        let r2 = crate::bababoi::random_double();
+
+        let phi = 2.0 * std::f64::consts::PI * r1;
+        let x = phi.cos() * r2.sqrt();
+        let y = phi.sin() * r2.sqrt();
+        let z = (1.0 - r2).sqrt();
+
+        Vec3::new(x, y, z)
+    }
+
     // Getters
     pub fn x(&self) -> f64 {
         self.e[0]
@@ -219,6 +232,14 @@ impl Neg for Vec3 {
     }
 }
 
+impl Index<usize> for Vec3 {
+    type Output = f64;
+
+    fn index(&self, i: usize) -> &f64 {
+        &self.e[i]
+    }
+}
+
 /// Display for debugging
 impl fmt::Display for Vec3 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {